@@ -0,0 +1,148 @@
+//! Audio backend abstraction: where the sound for a [`TrackInfo`] comes from.
+//!
+//! An [`AudioSource`] finds [`AudioCandidate`]s for a track and fetches the
+//! chosen one to a local file, which the pipeline then transcodes and tags.
+//! [`YoutubeSource`](crate::youtube::YoutubeSource) (YouTube Music search plus
+//! yt-dlp) is the default; a local folder or a fixture-backed source for
+//! offline tests plugs in through [`Downloader::audio_source`](crate::Downloader::audio_source).
+//!
+//! Example
+//! ```no_run
+//! use std::path::{Path, PathBuf};
+//! use std::sync::Arc;
+//! use rustifydl::audio::{AudioCandidate, AudioSource};
+//! use rustifydl::progress::{ProgressObserver, TrackProgress};
+//! use rustifydl::{DownloadOptions, Downloader, Result, TrackInfo};
+//!
+//! /// Serves `<dir>/<Artists - Title>.wav` files.
+//! struct LocalFolder {
+//!     dir: PathBuf,
+//! }
+//!
+//! #[async_trait::async_trait]
+//! impl AudioSource for LocalFolder {
+//!     async fn candidates(&self, track: &TrackInfo) -> Result<Vec<AudioCandidate>> {
+//!         let file = self.dir.join(format!("{}.wav", track.display_name()));
+//!         Ok(file
+//!             .exists()
+//!             .then(|| AudioCandidate {
+//!                 id: file.display().to_string(),
+//!                 title: track.name.clone(),
+//!                 ..AudioCandidate::default()
+//!             })
+//!             .into_iter()
+//!             .collect())
+//!     }
+//!
+//!     async fn fetch(
+//!         &self,
+//!         candidate: &AudioCandidate,
+//!         dest: &Path,
+//!         _track: &TrackProgress,
+//!         _observer: &dyn ProgressObserver,
+//!     ) -> Result<PathBuf> {
+//!         let file = dest.with_extension("wav");
+//!         std::fs::copy(&candidate.id, &file)?;
+//!         Ok(file)
+//!     }
+//! }
+//!
+//! # async fn run(opts: DownloadOptions) -> Result<()> {
+//! let report = Downloader::new(opts, String::new())
+//!     .audio_source(Arc::new(LocalFolder { dir: "./fixtures".into() }))
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    Result,
+    progress::{ProgressObserver, TrackProgress},
+    source::{AlbumInfo, TrackInfo},
+};
+
+/// How a candidate was found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MatchMethod {
+    /// Searched by the track's ISRC, with a matching duration, title and
+    /// artist.
+    Isrc,
+    /// Searched by artist and title and picked by score.
+    #[default]
+    Text,
+    /// Taken from the release matched for the whole album.
+    Album,
+}
+
+impl fmt::Display for MatchMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MatchMethod::Isrc => "isrc",
+            MatchMethod::Text => "text",
+            MatchMethod::Album => "album",
+        })
+    }
+}
+
+/// Something an [`AudioSource`] can fetch for a track.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioCandidate {
+    /// Source-specific ID, e.g. a YouTube video ID or a file path.
+    pub id: String,
+    /// Title as the source reports it.
+    pub title: String,
+    /// Artist or channel names.
+    pub artists: Vec<String>,
+    /// Album name, if the source knows it.
+    pub album: Option<String>,
+    /// Length of the audio, if known.
+    pub duration: Option<Duration>,
+    /// How the candidate was found.
+    pub method: MatchMethod,
+}
+
+/// A backend that can find and fetch audio for a track.
+///
+/// Implementations are shared between concurrent download tasks.
+#[async_trait]
+pub trait AudioSource: Send + Sync {
+    /// Candidates for `track`, best match first. An empty list means nothing
+    /// was found.
+    async fn candidates(&self, track: &TrackInfo) -> Result<Vec<AudioCandidate>>;
+
+    /// One candidate per track of `album`, all from the same release, so a
+    /// whole album isn't pieced together from singles and compilations.
+    /// `tracks` are the album's tracks being downloaded; the result has one
+    /// entry for each, `None` where [`candidates`](Self::candidates) should
+    /// be asked instead. The default matches nothing.
+    async fn album_candidates(
+        &self,
+        album: &AlbumInfo,
+        tracks: &[TrackInfo],
+    ) -> Result<Vec<Option<AudioCandidate>>> {
+        let _ = album;
+        Ok(vec![None; tracks.len()])
+    }
+
+    /// Fetch `candidate` to a local file.
+    ///
+    /// `dest` is the path to write without an extension; the source picks the
+    /// extension and returns the path it actually wrote. Byte progress may be
+    /// reported to `observer` for `track`.
+    async fn fetch(
+        &self,
+        candidate: &AudioCandidate,
+        dest: &Path,
+        track: &TrackProgress,
+        observer: &dyn ProgressObserver,
+    ) -> Result<PathBuf>;
+}
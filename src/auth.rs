@@ -0,0 +1,449 @@
+//! Logging in to a Spotify account with the authorization code + PKCE flow.
+//!
+//! Client credentials can only read public catalog data. To download Liked
+//! Songs, saved albums or private and collaborative playlists, the user logs
+//! in once with [`PkceLogin`]. The refresh token is stored as a
+//! [`StoredLogin`] in the config dir, and later runs build a user
+//! [`SpotifySession`](crate::session::SpotifySession) from it.
+//!
+//! The redirect URI must be registered for the app in the Spotify developer
+//! dashboard; the default is `http://127.0.0.1:8888/callback`.
+//!
+//! Example
+//! ```no_run
+//! use rustifydl::auth::{PkceLogin, StoredLogin};
+//!
+//! # async fn run() -> rustifydl::Result<()> {
+//! let login = PkceLogin::new("<client_id>")
+//!     .run(|url| println!("Open {url} to log in"))
+//!     .await?;
+//! if let Some(path) = StoredLogin::default_path() {
+//!     login.save(&path)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use log::debug;
+use rand::{Rng, distr::Alphanumeric};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    Error, Result,
+    session::{percent_encode, write_private},
+};
+
+const ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+/// How long to wait for the browser to come back with a code.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How long one connection to the redirect listener may take to send its
+/// request. Browsers open sockets ahead of time that may never send one.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Redirect URI used when none is configured.
+pub const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+
+/// Scopes needed for Liked Songs, saved albums and private playlists.
+pub const SCOPES: [&str; 3] = [
+    "user-library-read",
+    "playlist-read-private",
+    "playlist-read-collaborative",
+];
+
+/// A refresh token saved after [`PkceLogin::run`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredLogin {
+    /// Client the token was issued to.
+    pub client_id: String,
+    /// Long-lived token used to get new access tokens.
+    pub refresh_token: String,
+}
+
+impl StoredLogin {
+    /// `<config dir>/RustifyDL/spotify_login.json`, next to the CLI's `config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("RustifyDL").join("spotify_login.json"))
+    }
+
+    /// Read a login saved with [`save`](Self::save). Returns `None` if there
+    /// is none or it was issued to another client.
+    pub fn load(path: &Path, client_id: &str) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str::<StoredLogin>(&content)
+            .ok()
+            .filter(|login| login.client_id == client_id)
+    }
+
+    /// Write the login to `path`, creating the directory if needed.
+    ///
+    /// The token grants access to the account, so on Unix the file is only
+    /// readable by the current user.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| Error::Config(e.to_string()))?;
+        write_private(path, content.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Interactive authorization code + PKCE login.
+pub struct PkceLogin {
+    client_id: String,
+    redirect_uri: String,
+    accounts_url: String,
+}
+
+impl PkceLogin {
+    /// Log in to the app with `client_id`. No client secret is needed.
+    pub fn new(client_id: impl Into<String>) -> Self {
+        PkceLogin {
+            client_id: client_id.into(),
+            redirect_uri: DEFAULT_REDIRECT_URI.to_string(),
+            accounts_url: ACCOUNTS_URL.to_string(),
+        }
+    }
+
+    /// Redirect URI registered for the app. It must be an `http://` loopback
+    /// address; a listener is started on its port.
+    pub fn redirect_uri(mut self, redirect_uri: impl Into<String>) -> Self {
+        self.redirect_uri = redirect_uri.into();
+        self
+    }
+
+    /// Base URL of the accounts service, `https://accounts.spotify.com` by default.
+    pub fn accounts_url(mut self, url: impl Into<String>) -> Self {
+        self.accounts_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Run the login: start the redirect listener, hand the authorization URL
+    /// to `open` (print it, launch a browser, ...), wait for Spotify to
+    /// redirect back, and exchange the code for a refresh token.
+    ///
+    /// Gives up with [`Error::SpotifyAuth`] if no redirect arrives within
+    /// five minutes.
+    pub async fn run(&self, open: impl FnOnce(&str)) -> Result<StoredLogin> {
+        let (address, path) = loopback_address(&self.redirect_uri)?;
+        let listener = TcpListener::bind(&address).await?;
+
+        let verifier = random_string(64);
+        let state = random_string(16);
+        open(&self.authorize_url(&code_challenge(&verifier), &state));
+
+        let code = timeout(LOGIN_TIMEOUT, wait_for_code(&listener, &path, &state))
+            .await
+            .map_err(|_| {
+                Error::SpotifyAuth("No login within five minutes, giving up".to_string())
+            })??;
+        debug!("Got an authorization code, exchanging it");
+        self.exchange_code(&code, &verifier).await
+    }
+
+    /// URL the user opens to grant access.
+    fn authorize_url(&self, challenge: &str, state: &str) -> String {
+        let query = [
+            ("client_id", self.client_id.as_str()),
+            ("response_type", "code"),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", challenge),
+            ("state", state),
+            ("scope", &SCOPES.join(" ")),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{key}={}", percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+        format!("{}/authorize?{query}", self.accounts_url)
+    }
+
+    async fn exchange_code(&self, code: &str, verifier: &str) -> Result<StoredLogin> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            refresh_token: String,
+        }
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/token", self.accounts_url))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("client_id", &self.client_id),
+                ("code_verifier", verifier),
+            ])
+            .send()
+            .await
+            .map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+        if !status.is_success() {
+            return Err(Error::SpotifyAuth(format!("{status}: {body}")));
+        }
+        let token: TokenResponse =
+            serde_json::from_str(&body).map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+        Ok(StoredLogin {
+            client_id: self.client_id.clone(),
+            refresh_token: token.refresh_token,
+        })
+    }
+}
+
+/// Split `http://127.0.0.1:8888/callback` into the address to listen on and the path.
+fn loopback_address(redirect_uri: &str) -> Result<(String, String)> {
+    let invalid = || {
+        Error::InvalidOptions(format!(
+            "Redirect URI must be http://<host>:<port>/<path>: {redirect_uri}"
+        ))
+    };
+    let rest = redirect_uri.strip_prefix("http://").ok_or_else(invalid)?;
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let (host, port) = authority.rsplit_once(':').ok_or_else(invalid)?;
+    port.parse::<u16>().map_err(|_| invalid())?;
+    let host = if host == "localhost" {
+        "127.0.0.1"
+    } else {
+        host
+    };
+    Ok((format!("{host}:{port}"), format!("/{path}")))
+}
+
+/// Serve the redirect listener until a request for `path` with the expected
+/// `state` arrives, and return its `code` parameter. Other requests (e.g.
+/// `/favicon.ico`) get a 404, callbacks with another state an error page;
+/// neither ends the wait, nor do connections that send nothing.
+async fn wait_for_code(listener: &TcpListener, path: &str, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let request = match timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+            Ok(Ok(request)) => request,
+            Ok(Err(e)) => {
+                debug!("Dropping a redirect listener connection: {e}");
+                continue;
+            }
+            Err(_) => {
+                debug!("Dropping a redirect listener connection that sent no request");
+                continue;
+            }
+        };
+        let target = request.split_whitespace().nth(1).unwrap_or_default();
+        let (request_path, query) = target.split_once('?').unwrap_or((target, ""));
+        if request_path != path {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            query.split('&').find_map(|pair| {
+                pair.strip_prefix(name)
+                    .and_then(|value| value.strip_prefix('='))
+                    .map(percent_decode)
+            })
+        };
+        if param("state").as_deref() != Some(state) {
+            // Not the redirect of this login, maybe a stale tab: keep waiting.
+            debug!("Ignoring a login callback with an unexpected state");
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "This login link is out of date. Use the one RustifyDL printed last.",
+            )
+            .await;
+            continue;
+        }
+        let result = if let Some(error) = param("error") {
+            Err(Error::SpotifyAuth(format!(
+                "Login was not granted: {error}"
+            )))
+        } else {
+            param("code")
+                .ok_or_else(|| Error::SpotifyAuth("Login callback had no code".to_string()))
+        };
+
+        match &result {
+            Ok(_) => {
+                respond(
+                    &mut stream,
+                    "200 OK",
+                    "Logged in to RustifyDL. You can close this tab.",
+                )
+                .await
+            }
+            Err(e) => respond(&mut stream, "200 OK", &e.to_string()).await,
+        }
+        return result;
+    }
+}
+
+/// Read an HTTP request up to the end of its headers.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Answer with a plain text page. The browser may already be gone, so
+/// errors are ignored.
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn random_string(len: usize) -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// `BASE64URL(SHA256(verifier))`, as required for the `S256` method.
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Decode `%XX` escapes and `+` as used in query strings and local file URIs.
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(b), _) => {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+            (None, b'+') => decoded.push(b' '),
+            (None, b) => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_challenge_matches_rfc_7636() {
+        // RFC 7636, appendix B.
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn loopback_address_of_redirect_uris() {
+        assert_eq!(
+            loopback_address("http://localhost:8888/callback").unwrap(),
+            ("127.0.0.1:8888".to_string(), "/callback".to_string())
+        );
+        assert_eq!(
+            loopback_address("http://127.0.0.1:9000").unwrap(),
+            ("127.0.0.1:9000".to_string(), "/".to_string())
+        );
+        for invalid in [
+            "http://127.0.0.1/callback",
+            "http://127.0.0.1:port/callback",
+            "https://127.0.0.1:8888/callback",
+            "127.0.0.1:8888/callback",
+        ] {
+            assert!(
+                matches!(loopback_address(invalid), Err(Error::InvalidOptions(_))),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn percent_decode_escapes_and_plus() {
+        assert_eq!(percent_decode("Tyler%2C+The+Creator"), "Tyler, The Creator");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    async fn get(address: std::net::SocketAddr, target: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_skips_stray_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let waiting =
+            tokio::spawn(async move { wait_for_code(&listener, "/callback", "abc").await });
+
+        // A preconnected socket that never sends a request.
+        let _idle = TcpStream::connect(address).await.unwrap();
+        let favicon = get(address, "/favicon.ico").await;
+        assert!(favicon.starts_with("HTTP/1.1 404"), "{favicon}");
+        let stale = get(address, "/callback?code=old&state=xyz").await;
+        assert!(stale.starts_with("HTTP/1.1 400"), "{stale}");
+        let missing = get(address, "/callback?code=old").await;
+        assert!(missing.starts_with("HTTP/1.1 400"), "{missing}");
+
+        let done = get(address, "/callback?code=a%2Bb&state=abc").await;
+        assert!(done.starts_with("HTTP/1.1 200"), "{done}");
+        assert_eq!(waiting.await.unwrap().unwrap(), "a+b");
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_reports_a_denied_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let waiting =
+            tokio::spawn(async move { wait_for_code(&listener, "/callback", "abc").await });
+
+        get(address, "/callback?error=access_denied&state=abc").await;
+        assert!(matches!(
+            waiting.await.unwrap(),
+            Err(Error::SpotifyAuth(message)) if message.contains("access_denied")
+        ));
+    }
+}
@@ -1,10 +1,23 @@
-use clap::Parser;
-use log::{error, info};
+use clap::{Args, Parser, Subcommand, builder::TypedValueParser};
+use indicatif::MultiProgress;
+use indicatif_log_bridge::LogWrapper;
+use log::{LevelFilter, error, info};
 use regex::Regex;
-use rustifydl::{DownloadOptions, download_spotify};
+use rustifydl::{
+    AudioFormat, Bitrate, DownloadOptions, DownloadReport, EntryStatus, ProgressObserver,
+    ReleaseType, ResourceKind, SpotifyResource, SpotifySource, TrackInfo, TrackOutcome, Verbosity,
+    auth::{DEFAULT_REDIRECT_URI, PkceLogin, StoredLogin},
+    download_spotify_with_observer,
+    progress::{IndicatifObserver, LogObserver},
+    report::NOT_DOWNLOADED_FILE,
+    resolve_query,
+    resource::parse_input_list,
+    session::default_user_token_cache,
+};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::sync::Arc;
 
 #[derive(Deserialize, Serialize)]
 struct Config {
@@ -13,9 +26,25 @@ struct Config {
 }
 
 #[derive(Parser, Clone)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
-    pub url: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
+    /// Spotify URLs or URIs, `liked`, `saved-albums` or `me/playlists`; `-` reads a list from stdin
+    pub urls: Vec<String>,
+
+    /// File with one URL per line; `#` starts a comment, `-` is stdin
+    #[arg(long = "input-file", short = 'i')]
+    pub input_files: Vec<String>,
+
+    #[command(flatten)]
+    pub download: DownloadArgs,
+}
+
+/// Options shared by every command that downloads.
+#[derive(Args, Clone)]
+pub struct DownloadArgs {
     #[arg(long = "client-id")]
     pub client_id: Option<String>,
 
@@ -37,9 +66,9 @@ pub struct Cli {
         default_value = "192k",
         value_parser = clap::builder::PossibleValuesParser::new([
             "8k", "16k", "24k", "32k", "40k", "48k", "64k", "80k", "96k", "112k", "128k", "160k", "192k", "224k", "256k", "320k"
-        ])
+        ]).map(|s| s.parse::<Bitrate>().unwrap())
     )]
-    pub bitrate: String,
+    pub bitrate: Bitrate,
 
     #[arg(
         long = "format",
@@ -47,9 +76,9 @@ pub struct Cli {
         default_value = "mp3",
         value_parser = clap::builder::PossibleValuesParser::new([
             "mp3", "flac", "ogg", "opus", "m4a", "wav"
-        ])
+        ]).map(|s| s.parse::<AudioFormat>().unwrap())
     )]
-    pub format: String,
+    pub format: AudioFormat,
 
     #[arg(
         long = "verbosity",
@@ -57,44 +86,403 @@ pub struct Cli {
         default_value = "info",
         value_parser = clap::builder::PossibleValuesParser::new([
             "info", "debug", "error", "none", "full", "no-bars"
-        ])
+        ]).map(|s| s.parse::<Verbosity>().unwrap())
     )]
-    pub verbosity: String,
+    pub verbosity: Verbosity,
 
     #[arg(long = "no-tag", action = clap::ArgAction::SetTrue)]
     pub no_tag: bool,
 
     #[arg(long = "ytdlp-dir", short, default_value = "")]
     pub ytdlp_dir: String,
+
+    #[arg(
+        long = "release-types",
+        value_delimiter = ',',
+        default_value = "album,single,compilation",
+        value_parser = clap::builder::PossibleValuesParser::new([
+            "album", "single", "compilation", "appears_on"
+        ]).map(|s| s.parse::<ReleaseType>().unwrap())
+    )]
+    pub release_types: Vec<ReleaseType>,
+
+    /// Country code (e.g. DE) to look tracks up in; tracks not playable there are reported, not downloaded
+    #[arg(long = "market", value_name = "CC")]
+    pub market: Option<String>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Log in to your Spotify account to download Liked Songs, saved albums and private playlists
+    Login {
+        #[arg(long = "client-id")]
+        client_id: Option<String>,
+
+        /// Must be registered for the app in the Spotify developer dashboard
+        #[arg(long = "redirect-uri", default_value = DEFAULT_REDIRECT_URI)]
+        redirect_uri: String,
+    },
+    /// Search Spotify for a track, e.g. "Artist - Title", and download the chosen hit
+    Search {
+        /// Free text, e.g. "Daft Punk - One More Time"
+        query: String,
+
+        /// Download the top hit without asking
+        #[arg(long = "first", action = clap::ArgAction::SetTrue)]
+        first: bool,
+
+        /// Number of hits to show
+        #[arg(long = "limit", short = 'n', default_value_t = 5)]
+        limit: usize,
+
+        #[command(flatten)]
+        download: DownloadArgs,
+    },
+    /// Download the tracks listed in an Exportify CSV or a Spotify data export JSON (YourLibrary.json, Playlist1.json)
+    Import {
+        /// Export files; rows without a Spotify URI are searched for
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        #[command(flatten)]
+        download: DownloadArgs,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Cli::parse();
-    let (client_id, client_secret) =
-        if let (Some(id), Some(secret)) = (args.client_id, args.client_secret) {
-            (id, secret)
-        } else {
-            let config = check_api_keys().await?;
-            (config.client_id, config.client_secret)
-        };
+    match args.command {
+        Some(Command::Login {
+            client_id,
+            redirect_uri,
+        }) => login(client_id, redirect_uri).await,
+        Some(Command::Search {
+            query,
+            first,
+            limit,
+            download,
+        }) => {
+            let observer = init_logger(download.verbosity);
+            let credentials = credentials(&download).await?;
+            let source = SpotifySource::new(&credentials.0, &credentials.1)
+                .market(download.market.as_ref().map(|market| market.to_uppercase()));
+            let hits = resolve_query(&query, &source, limit).await?;
+            let Some(track) = pick_hit(&hits, first)? else {
+                return Ok(());
+            };
+            let input = SpotifyResource {
+                kind: ResourceKind::Track,
+                id: track.id.clone(),
+            };
+            run_download(vec![input.uri()], download, credentials, observer).await
+        }
+        Some(Command::Import { files, download }) => {
+            let observer = init_logger(download.verbosity);
+            let credentials = credentials(&download).await?;
+            run_download(files, download, credentials, observer).await
+        }
+        None => {
+            let observer = init_logger(args.download.verbosity);
+            let inputs = collect_inputs(&args.urls, &args.input_files)?;
+            if inputs.is_empty() {
+                return Err(
+                    "No Spotify URL given. Pass URLs, --input-file <PATH> or - for stdin.".into(),
+                );
+            }
+            let credentials = credentials(&args.download).await?;
+            run_download(inputs, args.download, credentials, observer).await
+        }
+    }
+}
+
+/// Client ID and secret from the command line, or else from the config file.
+async fn credentials(
+    args: &DownloadArgs,
+) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    if let (Some(id), Some(secret)) = (&args.client_id, &args.client_secret) {
+        return Ok((id.clone(), secret.clone()));
+    }
+    let config = check_api_keys().await?;
+    Ok((config.client_id, config.client_secret))
+}
+
+/// Download `inputs` with the options in `args`, print the summary and exit
+/// with status 1 if any track failed.
+async fn run_download(
+    inputs: Vec<String>,
+    args: DownloadArgs,
+    (client_id, client_secret): (String, String),
+    observer: Arc<dyn ProgressObserver>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut inputs = inputs.into_iter();
+    let mut builder = DownloadOptions::builder(inputs.next().unwrap_or_default())
+        .urls(inputs)
+        .client_credentials(client_id, client_secret)
+        .output_dir(args.output_dir)
+        .concurrent_downloads(args.concurrent_downloads)
+        .no_dupes(args.no_dupes)
+        .bitrate(args.bitrate)
+        .format(args.format)
+        .verbosity(args.verbosity)
+        .no_tag(args.no_tag)
+        .release_types(args.release_types);
+    if let Some(market) = args.market {
+        builder = builder.market(market);
+    }
+    let options = builder.build()?;
+    let output_dir = options.output_dir.clone();
+    let report = download_spotify_with_observer(options, args.ytdlp_dir, observer).await?;
+    print_summary(&report, &output_dir);
+    if report.has_failures() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// List search hits and let the user pick one; `first` takes the top hit.
+/// Returns `None` if the user cancels.
+fn pick_hit(
+    hits: &[TrackInfo],
+    first: bool,
+) -> Result<Option<&TrackInfo>, Box<dyn std::error::Error + Send + Sync>> {
+    if hits.is_empty() {
+        return Err("No tracks found on Spotify.".into());
+    }
+    for (n, hit) in hits.iter().enumerate() {
+        let year = hit
+            .album
+            .year()
+            .map(|year| format!(", {year}"))
+            .unwrap_or_default();
+        println!(
+            "{:>2}. {} ({}{year})",
+            n + 1,
+            hit.display_name(),
+            hit.album.name
+        );
+    }
+    if first {
+        return Ok(hits.first());
+    }
+    loop {
+        print!(
+            "Pick a track [1-{}, Enter for 1, q to cancel]: ",
+            hits.len()
+        );
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        match answer.trim() {
+            "" => return Ok(hits.first()),
+            "q" | "Q" => return Ok(None),
+            answer => match answer.parse::<usize>() {
+                Ok(n) if (1..=hits.len()).contains(&n) => return Ok(hits.get(n - 1)),
+                _ => println!("Enter a number between 1 and {}.", hits.len()),
+            },
+        }
+    }
+}
 
-    let options = DownloadOptions {
-        url: args.url,
-        client_id,
-        client_secret,
-        output_dir: args.output_dir,
-        concurrent_downloads: args.concurrent_downloads,
-        no_dupes: args.no_dupes,
-        bitrate: args.bitrate,
-        format: args.format,
-        verbosity: args.verbosity,
-        no_tag: args.no_tag,
+/// Run the PKCE login in the browser and store the refresh token.
+async fn login(
+    client_id: Option<String>,
+    redirect_uri: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client_id = match client_id {
+        Some(id) => id,
+        None => check_api_keys().await?.client_id,
     };
-    download_spotify(options, args.ytdlp_dir).await?;
+    let login = PkceLogin::new(client_id)
+        .redirect_uri(redirect_uri)
+        .run(|url| {
+            println!(
+                "Opening your browser to log in to Spotify. If it doesn't open, visit:\n{url}"
+            );
+            open_browser(url);
+        })
+        .await?;
+
+    let path = StoredLogin::default_path().ok_or("Could not find a valid config directory.")?;
+    login.save(&path)?;
+    // A token cached for a previous login may belong to another account.
+    if let Some(cache) = default_user_token_cache() {
+        let _ = fs::remove_file(cache);
+    }
+    println!("Logged in. Login saved to: {}", path.display());
+    println!("You can now download `liked`, `saved-albums` and `me/playlists`.");
     Ok(())
 }
 
+/// Best effort: the URL is printed as well, in case no browser can be started.
+fn open_browser(url: &str) {
+    let mut command = if cfg!(target_os = "windows") {
+        // Unlike `cmd /C start`, this doesn't split the URL at `&`.
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    if let Err(e) = command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    {
+        eprintln!("Couldn't open a browser: {e}");
+    }
+}
+
+/// Expand positional URLs and `--input-file` lists into the run's inputs, in
+/// the order given. `-`, as a URL or as a file name, reads a list from stdin.
+fn collect_inputs(urls: &[String], input_files: &[String]) -> std::io::Result<Vec<String>> {
+    let mut stdin = None;
+    let mut read_list = |path: &str| -> std::io::Result<Vec<String>> {
+        if path == "-" {
+            if stdin.is_none() {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text)?;
+                stdin = Some(parse_input_list(&text));
+            }
+            // Stdin can only be read once; later `-`s add nothing.
+            return Ok(stdin.replace(Vec::new()).unwrap_or_default());
+        }
+        Ok(parse_input_list(&fs::read_to_string(path)?))
+    };
+
+    let mut inputs = Vec::new();
+    for url in urls {
+        if url == "-" {
+            inputs.extend(read_list(url)?);
+        } else {
+            inputs.push(url.clone());
+        }
+    }
+    for path in input_files {
+        inputs.extend(read_list(path)?);
+    }
+    Ok(inputs)
+}
+
+/// Install the global logger for `verbosity` and return the matching progress
+/// observer. With progress bars, log lines are routed through the same
+/// `MultiProgress` so they don't tear the bars.
+fn init_logger(verbosity: Verbosity) -> Arc<dyn ProgressObserver> {
+    let mut logger = match verbosity {
+        Verbosity::Full => {
+            let mut builder = env_logger::Builder::new();
+            builder
+                .format(|buf, record| writeln!(buf, "{}", record.args()))
+                .filter_level(LevelFilter::Trace);
+            builder
+        }
+        Verbosity::Info | Verbosity::NoBars => {
+            let mut builder = env_logger::Builder::new();
+            builder
+                .format(|buf, record| writeln!(buf, "{}", record.args()))
+                .filter_level(LevelFilter::Off)
+                .filter_module("rustifydl", LevelFilter::Info);
+            builder
+        }
+        Verbosity::Debug => {
+            let mut builder = env_logger::Builder::new();
+            builder.filter_level(LevelFilter::Debug);
+            builder
+        }
+        Verbosity::None => {
+            let mut builder = env_logger::Builder::new();
+            builder
+                .format(|buf, record| writeln!(buf, "{}", record.args()))
+                .filter_level(LevelFilter::Off);
+            builder
+        }
+        Verbosity::Error => {
+            let mut builder = env_logger::Builder::new();
+            builder
+                .format(|buf, record| writeln!(buf, "{}", record.args()))
+                .filter_level(LevelFilter::Info)
+                .filter_module("spotify_rs", LevelFilter::Warn)
+                .filter_module("rustypipe_downloader", LevelFilter::Warn);
+            builder
+        }
+    };
+
+    if verbosity == Verbosity::NoBars {
+        logger.init();
+        Arc::new(LogObserver)
+    } else {
+        let multi = MultiProgress::new();
+        LogWrapper::new(multi.clone(), logger.build())
+            .try_init()
+            .expect("logger is only initialised once");
+        Arc::new(IndicatifObserver::new(multi))
+    }
+}
+
+fn print_summary(report: &DownloadReport, output_dir: &str) {
+    let width = report
+        .tracks
+        .iter()
+        .map(|track| track.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Track".len());
+    println!();
+    println!(
+        "{:<11} {:<width$} {:<11} {:<5} {:>7}",
+        "Status", "Track", "YouTube ID", "Match", "Time"
+    );
+    for track in &report.tracks {
+        let status = match track.outcome {
+            TrackOutcome::Pending => "pending",
+            TrackOutcome::Completed => "done",
+            TrackOutcome::Skipped => "skipped",
+            TrackOutcome::Failed(_) => "FAILED",
+            TrackOutcome::NotDownloadable(EntryStatus::LocalFile) => "local file",
+            TrackOutcome::NotDownloadable(EntryStatus::Removed) => "removed",
+            TrackOutcome::NotDownloadable(_) => "unavailable",
+        };
+        println!(
+            "{:<11} {:<width$} {:<11} {:<5} {:>6.1}s",
+            status,
+            track.name,
+            track.youtube_id.as_deref().unwrap_or("-"),
+            track
+                .match_method
+                .map_or("-".to_string(), |method| method.to_string()),
+            track.timings.total().as_secs_f32()
+        );
+    }
+    for track in &report.tracks {
+        match &track.outcome {
+            TrackOutcome::Failed(e) => println!("{}: {e}", track.name),
+            TrackOutcome::NotDownloadable(status) => println!("{}: {status}", track.name),
+            _ => {}
+        }
+    }
+    println!(
+        "{} completed, {} skipped, {} not downloadable, {} failed in {}s",
+        report.completed(),
+        report.skipped(),
+        report.not_downloadable(),
+        report.failed(),
+        report.elapsed.as_secs()
+    );
+    if report.not_downloadable() > 0 {
+        println!(
+            "Entries not downloaded are listed in {}",
+            std::path::Path::new(output_dir)
+                .join(NOT_DOWNLOADED_FILE)
+                .display()
+        );
+    }
+}
+
 async fn check_api_keys() -> Result<Config, Box<dyn std::error::Error + Send + Sync>> {
     let config_dir = dirs::config_dir().ok_or("Could not find a valid config directory.")?;
 
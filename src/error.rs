@@ -0,0 +1,160 @@
+//! Error type shared by the whole library.
+//!
+//! Every public function returns [`Result<T>`](Result), so callers can tell
+//! the failing stage apart without matching on strings:
+//! - Options: [`Error::InvalidOptions`]
+//! - URL parsing: [`Error::InvalidUrl`]
+//! - Spotify: [`Error::SpotifyAuth`], [`Error::Spotify`]
+//! - YouTube search: [`Error::YoutubeSearch`], [`Error::NoResults`]
+//! - Download/transcode: [`Error::YtDlp`], [`Error::Ffmpeg`], [`Error::YtDlpSetup`]
+//! - Tagging: [`Error::Tagging`], [`Error::Artwork`]
+//! - Import files: [`Error::Import`]
+//! - Track tasks: [`Error::TaskFailed`]
+
+use std::fmt;
+
+/// A convenience result type that uses [`enum@Error`] by default.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by RustifyDL, one variant per pipeline stage.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// [`DownloadOptions`](crate::DownloadOptions) failed validation.
+    InvalidOptions(String),
+    /// The input is not a Spotify URL RustifyDL knows how to handle.
+    InvalidUrl(String),
+    /// The input is valid but points to a resource that can't be downloaded.
+    Unsupported(String),
+    /// Exchanging the client credentials for an access token failed.
+    SpotifyAuth(String),
+    /// A Spotify API request failed.
+    Spotify {
+        /// HTTP status returned by the API, if the request got that far.
+        status: Option<u16>,
+        /// Error description from Spotify or the HTTP client.
+        message: String,
+    },
+    /// The YouTube Music search request itself failed.
+    YoutubeSearch(rustypipe::error::Error),
+    /// The YouTube Music search succeeded but returned nothing for the query.
+    NoResults(String),
+    /// Fetching, updating or verifying the yt-dlp binary failed.
+    YtDlpSetup(String),
+    /// yt-dlp exited with a non-zero status or produced no file.
+    YtDlp {
+        /// Exit code, if the process wasn't killed by a signal.
+        status: Option<i32>,
+        /// Captured stderr.
+        stderr: String,
+    },
+    /// ffmpeg exited with a non-zero status.
+    Ffmpeg {
+        /// Exit code, if the process wasn't killed by a signal.
+        status: Option<i32>,
+        /// Captured stderr.
+        stderr: String,
+    },
+    /// Reading or writing tags failed.
+    Tagging(lofty::error::LoftyError),
+    /// Downloading the cover art failed.
+    Artwork(reqwest::Error),
+    /// The config file couldn't be read or written.
+    Config(String),
+    /// An import file couldn't be parsed or lists nothing.
+    Import(String),
+    /// A track's download task panicked or was cancelled.
+    TaskFailed(String),
+    /// Any other filesystem or process I/O failure.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidOptions(msg) => write!(f, "Invalid options: {msg}"),
+            Error::InvalidUrl(url) => write!(f, "Invalid Spotify URL: {url}"),
+            Error::Unsupported(msg) => write!(f, "Unsupported input: {msg}"),
+            Error::SpotifyAuth(msg) => write!(f, "Spotify authentication failed: {msg}"),
+            Error::Spotify {
+                status: Some(status),
+                message,
+            } => write!(f, "Spotify API error {status}: {message}"),
+            Error::Spotify {
+                status: None,
+                message,
+            } => write!(f, "Spotify API error: {message}"),
+            Error::YoutubeSearch(e) => write!(f, "YouTube Music search failed: {e}"),
+            Error::NoResults(query) => write!(f, "No YouTube Music results for: {query}"),
+            Error::YtDlpSetup(msg) => write!(f, "yt-dlp setup failed: {msg}"),
+            Error::YtDlp { status, stderr } => {
+                write!(f, "yt-dlp failed ({}): {stderr}", exit_code(status))
+            }
+            Error::Ffmpeg { status, stderr } => {
+                write!(f, "ffmpeg failed ({}): {stderr}", exit_code(status))
+            }
+            Error::Tagging(e) => write!(f, "Failed to write tags: {e}"),
+            Error::Artwork(e) => write!(f, "Failed to download cover art: {e}"),
+            Error::Config(msg) => write!(f, "Config error: {msg}"),
+            Error::Import(msg) => write!(f, "Import failed: {msg}"),
+            Error::TaskFailed(msg) => write!(f, "Download task failed: {msg}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+fn exit_code(status: &Option<i32>) -> String {
+    match status {
+        Some(code) => format!("exit code {code}"),
+        None => "terminated by signal".to_string(),
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::YoutubeSearch(e) => Some(e),
+            Error::Tagging(e) => Some(e),
+            Error::Artwork(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Error::Io(source)
+    }
+}
+
+impl From<spotify_rs::Error> for Error {
+    fn from(source: spotify_rs::Error) -> Self {
+        match source {
+            spotify_rs::Error::Spotify {
+                status,
+                description,
+            } => Error::Spotify {
+                status: Some(status),
+                message: description,
+            },
+            spotify_rs::Error::Authentication { .. } => Error::SpotifyAuth(source.to_string()),
+            other => Error::Spotify {
+                status: None,
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl From<rustypipe::error::Error> for Error {
+    fn from(source: rustypipe::error::Error) -> Self {
+        Error::YoutubeSearch(source)
+    }
+}
+
+impl From<lofty::error::LoftyError> for Error {
+    fn from(source: lofty::error::LoftyError) -> Self {
+        Error::Tagging(source)
+    }
+}
@@ -0,0 +1,801 @@
+//! Importing track lists from library backups.
+//!
+//! Two formats are understood:
+//! - [Exportify](https://exportify.net) CSV, one playlist per file. The
+//!   `Track URI`, `Track Name`, `Artist Name(s)` and `Album Name` columns are
+//!   used; the file name becomes the playlist name.
+//! - Spotify's "Download your data" JSON: `YourLibrary.json` (Liked Songs and
+//!   saved albums) and `Playlist1.json`, `Playlist2.json`, ...
+//!
+//! [`ImportFile`] holds what a file lists. [`fetch_import`] turns it into the
+//! same track plan the fetchers produce: rows with a Spotify URI are looked up
+//! by ID, rows without one (local files, or tracks Spotify no longer knows)
+//! are searched for by title and artist.
+//!
+//! Example
+//! ```
+//! use rustifydl::import::ImportFile;
+//!
+//! let csv = "\
+//! Track URI,Track Name,Artist Name(s),Album Name
+//! spotify:track:4uLU6hMCjMI75M1A2tKUQC,Never Gonna Give You Up,Rick Astley,Whenever You Need Somebody
+//! spotify:local:::Demo+Tape:215,Demo Tape,\"Me, Myself\",
+//! ";
+//! let import = ImportFile::from_csv("Road Trip", csv).unwrap();
+//! let playlist = &import.playlists[0];
+//! assert_eq!(playlist.name, "Road Trip");
+//! assert_eq!(playlist.tracks[0].resource.as_ref().unwrap().id, "4uLU6hMCjMI75M1A2tKUQC");
+//! // Local files have no URI to look up, so they are searched for.
+//! assert!(playlist.tracks[1].resource.is_none());
+//! assert_eq!(playlist.tracks[1].query(), r#"track:"Demo Tape" artist:"Me""#);
+//! ```
+
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    DownloadOptions, Error, Result,
+    auth::percent_decode,
+    plan::{PlannedTrack, merge_plans, plan_albums, plan_playlists, plan_tracks},
+    resource::{ResourceKind, SpotifyResource},
+    source::{MetadataSource, PlaylistInfo, TrackInfo},
+};
+
+/// One row of an import file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportedTrack {
+    /// The track or episode the row names, if it has a usable Spotify URI.
+    pub resource: Option<SpotifyResource>,
+    /// Track title.
+    pub name: String,
+    /// Artist names, in credit order.
+    pub artists: Vec<String>,
+    /// Album title, if the file has one.
+    pub album: Option<String>,
+}
+
+impl ImportedTrack {
+    /// Catalog search query for rows without a URI, e.g.
+    /// `track:"One More Time" artist:"Daft Punk"`. Values are quoted so the
+    /// field filters cover every word; quotes inside them are dropped.
+    pub fn query(&self) -> String {
+        let quoted = |value: &str| format!("\"{}\"", value.replace('"', ""));
+        match self.artists.first() {
+            Some(artist) => format!("track:{} artist:{}", quoted(&self.name), quoted(artist)),
+            None => format!("track:{}", quoted(&self.name)),
+        }
+    }
+}
+
+impl fmt::Display for ImportedTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.artists.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} - {}", self.artists.join(", "), self.name)
+        }
+    }
+}
+
+/// A named track list from an import file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportedPlaylist {
+    /// Playlist name, used as its folder name.
+    pub name: String,
+    /// Rows in playlist order.
+    pub tracks: Vec<ImportedTrack>,
+}
+
+/// Everything an import file lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportFile {
+    /// Liked Songs, downloaded into the output folder like `liked`.
+    pub liked: Vec<ImportedTrack>,
+    /// Saved albums, by Spotify ID, one folder each like `saved-albums`.
+    pub albums: Vec<String>,
+    /// Playlists, one folder each.
+    pub playlists: Vec<ImportedPlaylist>,
+}
+
+impl ImportFile {
+    /// Read an Exportify `.csv` or a Spotify data export `.json` file.
+    ///
+    /// Returns [`Error::Import`] if the file can't be parsed or lists
+    /// nothing, and [`Error::Unsupported`] for other extensions.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let import = match extension(path).as_deref() {
+            Some("csv") => {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                ImportFile::from_csv(&name, &text)
+            }
+            Some("json") => ImportFile::from_json(&text),
+            _ => Err(Error::Unsupported(format!(
+                "{} is not a .csv or .json export",
+                path.display()
+            ))),
+        };
+        import.map_err(|e| match e {
+            Error::Import(msg) => Error::Import(format!("{}: {msg}", path.display())),
+            other => other,
+        })
+    }
+
+    /// Parse an Exportify CSV export of the playlist `name`.
+    pub fn from_csv(name: &str, text: &str) -> Result<Self> {
+        let mut rows = parse_csv(text).into_iter();
+        let header: Vec<String> = rows
+            .next()
+            .unwrap_or_default()
+            .iter()
+            .map(|column| column.trim().to_lowercase())
+            .collect();
+        let column = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| header.iter().position(|column| column == name))
+        };
+        let uri = column(&["track uri", "spotify uri", "uri"]);
+        let title = column(&["track name", "name", "title"]);
+        let artists = column(&["artist name(s)", "artist name", "artist"]);
+        let album = column(&["album name", "album"]);
+        if uri.is_none() && title.is_none() {
+            return Err(Error::Import(
+                "no \"Track URI\" or \"Track Name\" column".to_string(),
+            ));
+        }
+
+        let field = |row: &[String], index: Option<usize>| {
+            index
+                .and_then(|index| row.get(index))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let tracks = rows
+            .map(|row| ImportedTrack {
+                resource: field(&row, uri).and_then(|uri| track_resource(&uri)),
+                name: field(&row, title).unwrap_or_default(),
+                artists: field(&row, artists)
+                    .map(|names| split_artists(&names))
+                    .unwrap_or_default(),
+                album: field(&row, album),
+            })
+            .collect();
+        ImportFile {
+            playlists: vec![ImportedPlaylist {
+                name: name.to_string(),
+                tracks,
+            }],
+            ..ImportFile::default()
+        }
+        .non_empty()
+    }
+
+    /// Parse a `YourLibrary.json` or `PlaylistN.json` file from Spotify's
+    /// data export.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let export: DataExport = serde_json::from_str(text)
+            .map_err(|e| Error::Import(format!("not a Spotify data export: {e}")))?;
+        let liked = export
+            .tracks
+            .into_iter()
+            .map(|track| ImportedTrack {
+                resource: track.uri.as_deref().and_then(track_resource),
+                name: track.track,
+                artists: single_artist(&track.artist),
+                album: Some(track.album).filter(|album| !album.is_empty()),
+            })
+            .collect();
+        let albums = export
+            .albums
+            .into_iter()
+            .filter_map(|album| {
+                SpotifyResource::parse(&album.uri)
+                    .ok()
+                    .filter(|resource| resource.kind == ResourceKind::Album)
+                    .map(|resource| resource.id)
+            })
+            .collect();
+        let playlists = export
+            .playlists
+            .into_iter()
+            .map(|playlist| ImportedPlaylist {
+                name: playlist.name,
+                tracks: playlist
+                    .items
+                    .into_iter()
+                    .filter_map(PlaylistItem::into_imported)
+                    .collect(),
+            })
+            .collect();
+        ImportFile {
+            liked,
+            albums,
+            playlists,
+        }
+        .non_empty()
+    }
+
+    /// Number of rows, counting each saved album once.
+    pub fn len(&self) -> usize {
+        self.liked.len()
+            + self.albums.len()
+            + self
+                .playlists
+                .iter()
+                .map(|playlist| playlist.tracks.len())
+                .sum::<usize>()
+    }
+
+    /// Whether the file lists nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn non_empty(self) -> Result<Self> {
+        if self.is_empty() {
+            return Err(Error::Import(
+                "no tracks, albums or playlists found".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Whether an input names an import file rather than a URL or keyword:
+/// anything that isn't a URL and ends in `.csv` or `.json`.
+pub fn is_import_file(input: &str) -> bool {
+    let input = input.trim();
+    !input.contains("://")
+        && !input.starts_with("spotify:")
+        && matches!(extension(Path::new(input)).as_deref(), Some("csv" | "json"))
+}
+
+/// Plan the tracks listed in an import file.
+///
+/// Liked Songs go into the output folder, saved albums and playlists into a
+/// folder each, as for the `liked`, `saved-albums` and `me/playlists` inputs.
+/// Rows that can't be found or looked up in the catalog are logged and left
+/// out.
+pub async fn fetch_import(
+    path: &Path,
+    source: &dyn MetadataSource,
+    options: &DownloadOptions,
+) -> Result<Vec<PlannedTrack>> {
+    let import = ImportFile::read(path)?;
+    info!("Importing {} entries from {}", import.len(), path.display());
+
+    let liked = resolve_tracks(import.liked, source).await?;
+    let mut albums = Vec::new();
+    for id in import.albums {
+        let tracks = match source.album_tracks(&id).await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                warn!("Could not look up album {id}, leaving it out: {e}");
+                continue;
+            }
+        };
+        // Every track carries the full album, so it needn't be fetched again.
+        let Some(album) = tracks.first().map(|track| track.album.clone()) else {
+            warn!("Album {id} has no tracks, leaving it out");
+            continue;
+        };
+        albums.push((album, tracks));
+    }
+    let mut playlists = Vec::new();
+    for playlist in import.playlists {
+        let tracks = resolve_tracks(playlist.tracks, source).await?;
+        let info = PlaylistInfo {
+            name: playlist.name,
+            total_tracks: tracks.len() as u32,
+            ..PlaylistInfo::default()
+        };
+        playlists.push((info, tracks));
+    }
+
+    Ok(merge_plans(
+        vec![
+            plan_tracks(liked, options.no_dupes),
+            plan_albums(albums),
+            plan_playlists(playlists, options.no_dupes),
+        ],
+        options.no_dupes,
+    ))
+}
+
+/// Look the rows up in the catalog, keeping their order. Tracks with a URI
+/// are fetched in one batch, or one by one if the batch fails; the rest, and
+/// URIs the catalog doesn't know, are searched for.
+async fn resolve_tracks(
+    rows: Vec<ImportedTrack>,
+    source: &dyn MetadataSource,
+) -> Result<Vec<TrackInfo>> {
+    let mut ids: Vec<String> = Vec::new();
+    for row in &rows {
+        if let Some(SpotifyResource {
+            kind: ResourceKind::Track,
+            id,
+        }) = &row.resource
+            && !ids.contains(id)
+        {
+            ids.push(id.clone());
+        }
+    }
+    let mut found: HashMap<String, TrackInfo> = HashMap::new();
+    if !ids.is_empty() {
+        match source.tracks(&ids).await {
+            Ok(tracks) => found.extend(tracks.into_iter().map(|track| (track.id.clone(), track))),
+            Err(e) => {
+                // One bad ID fails its whole batch; the rest can still be found.
+                warn!(
+                    "Could not look up {} tracks at once, trying one by one: {e}",
+                    ids.len()
+                );
+                for id in &ids {
+                    match source.track(id).await {
+                        Ok(track) => {
+                            found.insert(id.clone(), track);
+                        }
+                        Err(e) => debug!("Could not look up track {id}, searching for it: {e}"),
+                    }
+                }
+            }
+        }
+    }
+
+    let mut tracks = Vec::with_capacity(rows.len());
+    let mut searched = 0;
+    for row in rows {
+        let track = match &row.resource {
+            Some(SpotifyResource {
+                kind: ResourceKind::Episode,
+                id,
+            }) => source.episode(id).await.map(Some),
+            Some(SpotifyResource { id, .. }) if found.contains_key(id) => {
+                Ok(found.get(id).cloned())
+            }
+            _ if row.name.is_empty() => Ok(None),
+            _ => {
+                searched += 1;
+                source
+                    .search_tracks(&row.query(), 1)
+                    .await
+                    .map(|hits| hits.into_iter().next())
+            }
+        };
+        // One bad row shouldn't cost the rest of the file.
+        match track {
+            Ok(Some(track)) => tracks.push(track),
+            Ok(None) => warn!("Could not find {row} on Spotify, leaving it out"),
+            Err(e) => warn!("Could not look up {row}, leaving it out: {e}"),
+        }
+    }
+    if searched > 0 {
+        info!("Searched for {searched} tracks without a Spotify URI");
+    }
+    Ok(tracks)
+}
+
+/// The track or episode a URI names. Local files (`spotify:local:...`) and
+/// other kinds give `None`.
+fn track_resource(uri: &str) -> Option<SpotifyResource> {
+    SpotifyResource::parse(uri)
+        .ok()
+        .filter(|resource| matches!(resource.kind, ResourceKind::Track | ResourceKind::Episode))
+}
+
+/// Exportify joins the artists of a track with commas.
+fn split_artists(names: &str) -> Vec<String> {
+    names.split(',').flat_map(single_artist).collect()
+}
+
+/// The data export names only the first artist, which may itself contain a
+/// comma ("Tyler, The Creator"), so it is kept whole.
+fn single_artist(name: &str) -> Vec<String> {
+    Some(name.trim())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .into_iter()
+        .collect()
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// Split RFC 4180 CSV into rows of fields: quoted fields may hold commas,
+/// line breaks and doubled quotes. Blank lines are dropped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    rows
+}
+
+/// `YourLibrary.json` and `PlaylistN.json` share no fields, so one model
+/// with defaults reads both.
+#[derive(Deserialize)]
+struct DataExport {
+    #[serde(default)]
+    tracks: Vec<LibraryTrack>,
+    #[serde(default)]
+    albums: Vec<LibraryAlbum>,
+    #[serde(default)]
+    playlists: Vec<ExportedPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct LibraryTrack {
+    #[serde(default)]
+    artist: String,
+    #[serde(default)]
+    album: String,
+    #[serde(default)]
+    track: String,
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LibraryAlbum {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct ExportedPlaylist {
+    name: String,
+    #[serde(default)]
+    items: Vec<PlaylistItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistItem {
+    track: Option<PlaylistTrack>,
+    episode: Option<PlaylistEpisode>,
+    local_track: Option<LocalTrack>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistTrack {
+    #[serde(default)]
+    track_name: String,
+    #[serde(default)]
+    artist_name: String,
+    #[serde(default)]
+    album_name: String,
+    track_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistEpisode {
+    #[serde(default)]
+    episode_name: String,
+    #[serde(default)]
+    show_name: String,
+    episode_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LocalTrack {
+    uri: String,
+}
+
+impl PlaylistItem {
+    fn into_imported(self) -> Option<ImportedTrack> {
+        if let Some(track) = self.track {
+            return Some(ImportedTrack {
+                resource: track.track_uri.as_deref().and_then(track_resource),
+                name: track.track_name,
+                artists: single_artist(&track.artist_name),
+                album: Some(track.album_name).filter(|album| !album.is_empty()),
+            });
+        }
+        if let Some(episode) = self.episode {
+            // Only songs can be searched for, so an episode needs its URI.
+            return Some(ImportedTrack {
+                resource: Some(episode.episode_uri.as_deref().and_then(track_resource)?),
+                name: episode.episode_name,
+                artists: vec![episode.show_name],
+                album: None,
+            });
+        }
+        self.local_track.map(|local| local_track(&local.uri))
+    }
+}
+
+/// Read artist, album and title out of a
+/// `spotify:local:<artist>:<album>:<title>:<seconds>` URI.
+fn local_track(uri: &str) -> ImportedTrack {
+    let fields: Vec<String> = uri
+        .strip_prefix("spotify:local:")
+        .unwrap_or_default()
+        .split(':')
+        .map(percent_decode)
+        .collect();
+    let field = |index: usize| fields.get(index).cloned().unwrap_or_default();
+    ImportedTrack {
+        resource: None,
+        name: field(2),
+        artists: single_artist(&field(0)),
+        album: Some(field(1)).filter(|album| !album.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::AlbumInfo;
+
+    const TRACK: &str = "4uLU6hMCjMI75M1A2tKUQC";
+    const EPISODE: &str = "512ojhOuo1ktJprKbVcKyQ";
+
+    fn resource(kind: ResourceKind, id: &str) -> Option<SpotifyResource> {
+        Some(SpotifyResource {
+            kind,
+            id: id.to_string(),
+        })
+    }
+
+    #[test]
+    fn csv_quoted_fields_keep_commas_quotes_and_line_breaks() {
+        let csv = format!(
+            "\u{feff}Track URI,Track Name,Artist Name(s),Album Name\r\n\
+             spotify:track:{TRACK},\"Hello, Goodbye\",\"Simon, Garfunkel\",\"The \"\"Best\"\"\nOf\"\r\n"
+        );
+        let import = ImportFile::from_csv("Mix", &csv).unwrap();
+        assert_eq!(
+            import.playlists[0].tracks,
+            [ImportedTrack {
+                resource: resource(ResourceKind::Track, TRACK),
+                name: "Hello, Goodbye".to_string(),
+                artists: vec!["Simon".to_string(), "Garfunkel".to_string()],
+                album: Some("The \"Best\"\nOf".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn csv_missing_columns_and_short_rows() {
+        let import = ImportFile::from_csv("Mix", "Track Name\nIntro\n\nOutro\n").unwrap();
+        let tracks = &import.playlists[0].tracks;
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[1].name, "Outro");
+        assert!(tracks[1].resource.is_none() && tracks[1].artists.is_empty());
+        assert_eq!(tracks[1].album, None);
+
+        let csv = "Track Name,Artist Name(s),Album Name\nIntro\n";
+        let import = ImportFile::from_csv("Mix", csv).unwrap();
+        assert_eq!(import.playlists[0].tracks[0].album, None);
+
+        let csv = "Artist Name(s),Album Name\nSomeone,Something\n";
+        assert!(matches!(
+            ImportFile::from_csv("Mix", csv),
+            Err(Error::Import(_))
+        ));
+        assert!(matches!(
+            ImportFile::from_csv("Mix", "Track URI,Track Name\n"),
+            Err(Error::Import(_))
+        ));
+    }
+
+    #[test]
+    fn csv_local_and_episode_uris() {
+        let csv = format!(
+            "Track URI,Track Name,Artist Name(s)\n\
+             spotify:local:Me:Tapes:Demo+Tape:215,Demo Tape,Me\n\
+             spotify:episode:{EPISODE},Episode 1,Some Show\n"
+        );
+        let import = ImportFile::from_csv("Mix", &csv).unwrap();
+        let tracks = &import.playlists[0].tracks;
+        assert_eq!(tracks[0].resource, None);
+        assert_eq!(tracks[0].query(), r#"track:"Demo Tape" artist:"Me""#);
+        let unnamed = ImportedTrack {
+            name: "12\" Mix".to_string(),
+            ..ImportedTrack::default()
+        };
+        assert_eq!(unnamed.query(), r#"track:"12 Mix""#);
+        assert_eq!(tracks[1].resource, resource(ResourceKind::Episode, EPISODE));
+    }
+
+    #[test]
+    fn json_library_keeps_artist_names_whole() {
+        let json = format!(
+            r#"{{
+                "tracks": [
+                    {{"artist": "Tyler, The Creator", "album": "IGOR", "track": "EARFQUAKE", "uri": "spotify:track:{TRACK}"}},
+                    {{"artist": "Me", "album": "", "track": "Demo", "uri": "spotify:local:Me::Demo:100"}}
+                ],
+                "albums": [
+                    {{"artist": "Someone", "album": "Something", "uri": "spotify:album:{TRACK}"}},
+                    {{"artist": "Someone", "album": "Broken", "uri": "not a uri"}}
+                ]
+            }}"#
+        );
+        let import = ImportFile::from_json(&json).unwrap();
+        assert_eq!(import.liked[0].artists, ["Tyler, The Creator"]);
+        assert_eq!(
+            import.liked[0].resource,
+            resource(ResourceKind::Track, TRACK)
+        );
+        assert_eq!(import.liked[1].resource, None);
+        assert_eq!(import.liked[1].album, None);
+        assert_eq!(import.albums, [TRACK]);
+        assert_eq!(import.len(), 3);
+    }
+
+    #[test]
+    fn json_playlist_items() {
+        let json = format!(
+            r#"{{"playlists": [{{
+                "name": "Mix",
+                "items": [
+                    {{"track": {{"trackName": "EARFQUAKE", "artistName": "Tyler, The Creator", "albumName": "IGOR", "trackUri": "spotify:track:{TRACK}"}}, "episode": null, "localTrack": null}},
+                    {{"track": null, "episode": {{"episodeName": "Episode 1", "showName": "Some Show", "episodeUri": "spotify:episode:{EPISODE}"}}, "localTrack": null}},
+                    {{"track": null, "episode": {{"episodeName": "Gone", "showName": "Some Show"}}, "localTrack": null}},
+                    {{"track": null, "episode": null, "localTrack": {{"uri": "spotify:local:Tyler%2C+The+Creator:Tapes:Demo+Tape:215"}}}}
+                ]
+            }}]}}"#
+        );
+        let import = ImportFile::from_json(&json).unwrap();
+        let tracks = &import.playlists[0].tracks;
+        // The episode without a URI can't be searched for, so it's dropped.
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[0].artists, ["Tyler, The Creator"]);
+        assert_eq!(tracks[1].resource, resource(ResourceKind::Episode, EPISODE));
+        assert_eq!(tracks[1].artists, ["Some Show"]);
+        assert_eq!(
+            tracks[2],
+            ImportedTrack {
+                resource: None,
+                name: "Demo Tape".to_string(),
+                artists: vec!["Tyler, The Creator".to_string()],
+                album: Some("Tapes".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn json_without_entries_is_an_error() {
+        assert!(matches!(ImportFile::from_json("{}"), Err(Error::Import(_))));
+        assert!(matches!(ImportFile::from_json("[]"), Err(Error::Import(_))));
+    }
+
+    /// Knows track `good` and album [`TRACK`], finds anything searched for
+    /// but "Broken", and fails every other lookup.
+    struct FlakySource;
+
+    fn found(id: &str, name: &str) -> TrackInfo {
+        let mut track = TrackInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            artists: vec!["Artist".to_string()],
+            ..TrackInfo::default()
+        };
+        track.album.name = "Album".to_string();
+        track
+    }
+
+    #[async_trait::async_trait]
+    impl MetadataSource for FlakySource {
+        async fn track(&self, id: &str) -> Result<TrackInfo> {
+            match id {
+                "good" => Ok(found("good", "Good")),
+                _ => Err(Error::NoResults(id.to_string())),
+            }
+        }
+
+        async fn album_tracks(&self, id: &str) -> Result<Vec<TrackInfo>> {
+            match id {
+                TRACK => Ok(vec![found("album-track", "Album Track")]),
+                _ => Err(Error::NoResults(id.to_string())),
+            }
+        }
+
+        async fn playlist_tracks(&self, id: &str) -> Result<Vec<TrackInfo>> {
+            Err(Error::NoResults(id.to_string()))
+        }
+
+        async fn album(&self, id: &str) -> Result<AlbumInfo> {
+            Err(Error::NoResults(id.to_string()))
+        }
+
+        async fn search_tracks(&self, query: &str, _: usize) -> Result<Vec<TrackInfo>> {
+            match query {
+                r#"track:"Broken" artist:"Artist""# => Err(Error::NoResults(query.to_string())),
+                _ => Ok(vec![found("searched", query)]),
+            }
+        }
+    }
+
+    fn row(id: Option<&str>, name: &str) -> ImportedTrack {
+        ImportedTrack {
+            resource: id.and_then(|id| resource(ResourceKind::Track, id)),
+            name: name.to_string(),
+            artists: vec!["Artist".to_string()],
+            album: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_fall_back_or_skip_the_row() {
+        // The default `tracks` fails the whole batch on "bad".
+        let tracks = resolve_tracks(
+            vec![
+                row(Some("good"), "Good"),
+                row(Some("bad"), "Bad"),
+                row(None, "Broken"),
+                row(None, "Local"),
+            ],
+            &FlakySource,
+        )
+        .await
+        .unwrap();
+        let names: Vec<_> = tracks.iter().map(|track| track.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Good",
+                r#"track:"Bad" artist:"Artist""#,
+                r#"track:"Local" artist:"Artist""#
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_albums_are_left_out() {
+        let path =
+            std::env::temp_dir().join(format!("rustifydl-import-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"albums": [
+                {"uri": "spotify:album:4uLU6hMCjMI75M1A2tKUQC"},
+                {"uri": "spotify:album:512ojhOuo1ktJprKbVcKyQ"}
+            ]}"#,
+        )
+        .unwrap();
+        let plan = fetch_import(&path, &FlakySource, &DownloadOptions::default()).await;
+        fs::remove_file(&path).unwrap();
+        let plan = plan.unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].relative_name(), "Album/Artist - Album Track");
+    }
+
+    #[test]
+    fn import_files_are_told_from_urls() {
+        assert!(is_import_file("Playlist1.json"));
+        assert!(is_import_file("./exports/Road Trip.CSV"));
+        assert!(!is_import_file("https://example.com/list.csv"));
+        assert!(!is_import_file("liked"));
+    }
+}
@@ -5,16 +5,20 @@
 //! YouTube, and write clean tags to files.
 //!
 //! Key items:
-//! - [`DownloadOptions`] input options
+//! - [`DownloadOptions`] input options, built directly or via [`DownloadOptions::builder`]
 //! - `ytdlp_dir` ytdlp binary download directory
 //! - [`download_spotify`] to drive the whole flow asynchronously
+//! - [`download_spotify_with_observer`] to receive [`ProgressEvent`]s instead of progress bars
+//! - [`Downloader`] to plug in a custom [`MetadataSource`], [`AudioSource`], [`Transcoder`] or observer
+//! - [`Error`] returned by every fallible function, one variant per stage
+//! - [`DownloadReport`] per-track outcome and timings of a run
 //!
 //! Examples
 //! ```no_run
-//! use rustifydl::{download_spotify, DownloadOptions};
+//! use rustifydl::{download_spotify, AudioFormat, Bitrate, DownloadOptions, Verbosity};
 //!
 //! #[tokio::main]
-//! async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! async fn main() -> Result<(), rustifydl::Error> {
 //!     let opts = DownloadOptions {
 //!         url: "https://open.spotify.com/album/xxxxxxxx".into(),
 //!         client_id: "<client_id>".into(),
@@ -22,13 +26,15 @@
 //!         output_dir: "./output".into(),
 //!         concurrent_downloads: 6,
 //!         no_dupes: true,
-//!         bitrate: "192k".into(),
-//!         format: "mp3".into(),
-//!         verbosity: "info".into(),
+//!         bitrate: Bitrate::K192,
+//!         format: AudioFormat::Mp3,
+//!         verbosity: Verbosity::Info,
 //!         no_tag: false,
+//!         ..DownloadOptions::default()
 //!     };
 //! let ytdlp_dir = String::from("./ytdlp");
-//! download_spotify(opts, ytdlp_dir).await?;
+//! let report = download_spotify(opts, ytdlp_dir).await?;
+//! println!("{} downloaded, {} failed", report.completed(), report.failed());
 //! Ok(())
 //! }
 //! ```
@@ -36,60 +42,62 @@
 #![allow(clippy::module_inception)]
 use {
     crate::{
-        metadata::metadata,
-        spotify::{fetch_album, fetch_playlist, fetch_track},
-        youtube::{DownloadResult, download_ytdlp, search_yt},
+        import::{fetch_import, is_import_file},
+        metadata::{ArtworkCache, metadata},
+        plan::merge_plans,
+        progress::{IndicatifObserver, LogObserver, TrackProgress},
+        spotify::{
+            fetch_album, fetch_artist, fetch_episode, fetch_liked, fetch_playlist,
+            fetch_saved_albums, fetch_show, fetch_track, fetch_user_playlists,
+        },
+        youtube::{DownloadResult, YoutubeSource, download_ytdlp},
     },
-    indicatif::{MultiProgress, ProgressBar, ProgressStyle},
-    indicatif_log_bridge::LogWrapper,
-    log::{LevelFilter, error, info},
+    indicatif::MultiProgress,
+    log::{error, info, warn},
     regex::Regex,
-    spotify_rs::model::track::Track,
     std::{
         collections::HashMap,
         fs::{self, remove_dir_all},
-        io::Write,
-        path::PathBuf,
+        path::{Path, PathBuf},
         sync::Arc,
-        time::{Duration, Instant},
+        time::Instant,
     },
     tokio::sync::Semaphore,
 };
 
+pub mod audio;
+pub mod auth;
+pub mod error;
+pub mod import;
+pub mod matching;
 pub mod metadata;
+pub mod options;
+pub mod plan;
+pub mod progress;
+pub mod report;
+pub mod resource;
+pub mod session;
+pub mod source;
 pub mod spotify;
+pub mod transcode;
 pub mod youtube;
 
-/// Options used to control how downloads are performed.
-///
-/// These map to CLI flags in the binary.
-pub struct DownloadOptions {
-    /// Spotify URL (track/album/playlist)
-    pub url: String,
-    /// Spotify Client ID
-    pub client_id: String,
-    /// Spotify Client Secret
-    pub client_secret: String,
-    /// Destination folder for audio files
-    pub output_dir: String,
-    /// Maximum number of concurrent downloads
-    pub concurrent_downloads: usize,
-    /// Skip duplicate tracks across collections
-    pub no_dupes: bool,
-    /// Target audio bitrate for ffmpeg (e.g., "192k")
-    pub bitrate: String,
-    /// Output format/extension (e.g., "mp3")
-    pub format: String,
-    /// Log verbosity level:
-    /// - `"full"`: All debug information with progress bars
-    /// - `"info"`: Standard information with progress bars  
-    /// - `"debug"`: Debug level logging with progress bars
-    /// - `"no-bars"`: Clean stdout output without progress bars (ideal for scripts/CI)
-    /// - `"none"`: Minimal output with progress bars
-    pub verbosity: String,
-    /// Don't write audio tags or cover art
-    pub no_tag: bool,
-}
+pub use audio::{AudioCandidate, AudioSource, MatchMethod};
+pub use auth::{PkceLogin, StoredLogin};
+pub use error::{Error, Result};
+pub use import::ImportFile;
+pub use options::{
+    AudioFormat, Bitrate, DownloadOptions, DownloadOptionsBuilder, ReleaseType, Verbosity,
+};
+pub use plan::PlannedTrack;
+pub use progress::{ProgressEvent, ProgressObserver};
+pub use report::{DownloadReport, StageTimings, TrackOutcome, TrackReport};
+pub use resource::{
+    LibraryInput, ResourceKind, SpotifyResource, is_short_link, resolve_short_link,
+};
+pub use source::{AlbumInfo, EntryStatus, MetadataSource, PlaylistInfo, TrackInfo, TrackKind};
+pub use spotify::{SpotifySource, resolve_query};
+pub use transcode::{FfmpegTranscoder, OutputSpec, Transcoder};
 
 fn sanitize_filename(name: &str) -> String {
     let re = Regex::new(r#"[<>:"/\\|?*\x00-\x1F]"#).unwrap();
@@ -98,8 +106,10 @@ fn sanitize_filename(name: &str) -> String {
 
 /// Extract a Spotify ID from a typical Spotify URL.
 ///
-/// Supports `track/`, `album/`, `playlist/`, and `artist/` URL shapes.
-/// Returns `Some(id)` when an ID is present; otherwise `None`.
+/// Supports `track/`, `album/`, `playlist/`, and `artist/` URL shapes, as well
+/// as `spotify:` URIs. Returns `Some(id)` when an ID is present; otherwise
+/// `None`. Use [`SpotifyResource::parse`] to also get the kind and the reason
+/// a link was rejected.
 ///
 /// Example
 /// ```
@@ -108,66 +118,40 @@ fn sanitize_filename(name: &str) -> String {
 /// assert!(id.is_some());
 /// ```
 pub fn extract_id_from_url(url: &str) -> Option<String> {
-    let re = Regex::new(r"(track|album|playlist|artist)/([a-zA-Z0-9]+)").unwrap();
-
-    if let Some(captures) = re.captures(url) {
-        return captures.get(2).map(|id| id.as_str().to_string());
-    }
-
-    None
-}
-
-const SPOTIFY_PATTERNS: [&str; 3] = [
-    r"^https://open\.spotify\.com/(track|album|playlist|artist)/.+",
-    r"^spotify:(track|album|playlist|artist):.+",
-    r"^https://spotify\.link/.+",
-];
-
-enum SpotifyUrlType {
-    Track,
-    Album,
-    Playlist,
-    Artist,
-}
-
-fn is_valid_spotify_url(url: &str) -> Option<(SpotifyUrlType, String)> {
-    for pattern in SPOTIFY_PATTERNS.iter() {
-        let re = Regex::new(pattern).unwrap();
-        if re.is_match(url.trim()) {
-            let id = extract_id_from_url(url)?;
-            if url.contains("track") {
-                return Some((SpotifyUrlType::Track, id));
-            } else if url.contains("album") {
-                return Some((SpotifyUrlType::Album, id));
-            } else if url.contains("playlist") {
-                return Some((SpotifyUrlType::Playlist, id));
-            } else if url.contains("artist") {
-                error!("You wouldn't download an Artist!");
-                return Some((SpotifyUrlType::Artist, id));
-            }
-        }
-    }
-    None
+    SpotifyResource::parse(url).ok().map(|resource| resource.id)
 }
 
 /// Resolve a Spotify URL and download all corresponding tracks.
 ///
 /// Steps:
-/// 1. Determine URL type (track/album/playlist) and fetch tracks from Spotify.
+/// 1. Determine URL type (track/album/playlist/artist) and fetch tracks from Spotify.
+///    With several inputs (`url` plus `urls`), tracks planned by an earlier
+///    input are skipped.
 /// 2. For each track, search YouTube and download best audio stream.
 /// 3. Optionally write tags and artwork (`no_tag == false`).
 ///
+/// Individual track failures don't abort the run; they are collected in the
+/// returned [`DownloadReport`]. Only setup errors (invalid options, bad URL,
+/// Spotify lookup, yt-dlp install) return `Err`.
+///
 /// ## Verbosity Modes
 ///
 /// The `verbosity` field controls output behavior:
-/// - **Progress bars mode** (`"full"`, `"info"`, `"debug"`, `"none"`): Shows interactive progress bars
-/// - **No-bars mode** (`"no-bars"`): Clean stdout logging, ideal for automation and CI/CD
+/// - **Progress bars mode** ([`Verbosity::Full`], [`Verbosity::Info`],
+///   [`Verbosity::Debug`], [`Verbosity::Error`], [`Verbosity::None`]): Shows
+///   interactive progress bars
+/// - **No-bars mode** ([`Verbosity::NoBars`]): Progress is emitted as `log`
+///   records, ideal for automation and CI/CD
+///
+/// The library never installs a logger; records go to whatever `log`
+/// implementation the host set up, so this can be called any number of times
+/// in one process.
 ///
 /// Example with no-bars mode for clean output:
 /// ```no_run
-/// use rustifydl::{download_spotify, DownloadOptions};
+/// use rustifydl::{download_spotify, AudioFormat, Bitrate, DownloadOptions, Verbosity};
 /// # #[tokio::main]
-/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// # async fn main() -> Result<(), rustifydl::Error> {
 /// let opts = DownloadOptions {
 ///     url: "https://open.spotify.com/playlist/xxxxxxxx".into(),
 ///     client_id: "<client_id>".into(),
@@ -175,10 +159,11 @@ fn is_valid_spotify_url(url: &str) -> Option<(SpotifyUrlType, String)> {
 ///     output_dir: "./output".into(),
 ///     concurrent_downloads: 8,
 ///     no_dupes: true,
-///     bitrate: "192k".into(),
-///     format: "mp3".into(),
-///     verbosity: "no-bars".into(), // Clean output for scripts
+///     bitrate: Bitrate::K192,
+///     format: AudioFormat::Mp3,
+///     verbosity: Verbosity::NoBars, // Clean output for scripts
 ///     no_tag: false,
+///     ..DownloadOptions::default()
 /// };
 /// let ytdlp_dir = String::from("./ytdlp");
 /// download_spotify(opts, ytdlp_dir).await?;
@@ -188,9 +173,9 @@ fn is_valid_spotify_url(url: &str) -> Option<(SpotifyUrlType, String)> {
 ///
 /// Example
 /// ```no_run
-/// use rustifydl::{download_spotify, DownloadOptions};
+/// use rustifydl::{download_spotify, AudioFormat, Bitrate, DownloadOptions, Verbosity};
 /// # #[tokio::main]
-/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// # async fn main() -> Result<(), rustifydl::Error> {
 /// let opts = DownloadOptions {
 ///     url: "https://open.spotify.com/playlist/xxxxxxxx".into(),
 ///     client_id: "<client_id>".into(),
@@ -198,10 +183,11 @@ fn is_valid_spotify_url(url: &str) -> Option<(SpotifyUrlType, String)> {
 ///     output_dir: "./output".into(),
 ///     concurrent_downloads: 8,
 ///     no_dupes: true,
-///     bitrate: "192k".into(),
-///     format: "mp3".into(),
-///     verbosity: "info".into(),
+///     bitrate: Bitrate::K192,
+///     format: AudioFormat::Mp3,
+///     verbosity: Verbosity::Info,
 ///     no_tag: false,
+///     ..DownloadOptions::default()
 /// };
 ///
 /// let ytdlp_dir = String::from("./ytdlp");
@@ -212,176 +198,411 @@ fn is_valid_spotify_url(url: &str) -> Option<(SpotifyUrlType, String)> {
 pub async fn download_spotify(
     options: DownloadOptions,
     ytdlp_dir: String,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let multi = MultiProgress::new();
-    let start_time = Instant::now();
-    let no_bars = options.verbosity.clone() == "no-bars";
-    let mut logger = match options.verbosity.clone().as_str() {
-        "full" => {
-            let mut builder = env_logger::Builder::new();
-            builder
-                .format(|buf, record| writeln!(buf, "{}", record.args()))
-                .filter_level(LevelFilter::Trace);
-            builder
-        }
-        "info" | "no-bars" => {
-            let mut builder = env_logger::Builder::new();
-            builder
-                .format(|buf, record| writeln!(buf, "{}", record.args()))
-                .filter_level(LevelFilter::Off)
-                .filter_module("rustifydl", LevelFilter::Info);
-            builder
+) -> Result<DownloadReport> {
+    Downloader::new(options, ytdlp_dir).run().await
+}
+
+/// Same as [`download_spotify`], but reports progress to `observer` instead of
+/// picking an observer from `options.verbosity`.
+///
+/// See [`progress`] for the events emitted and the bundled observers.
+pub async fn download_spotify_with_observer(
+    options: DownloadOptions,
+    ytdlp_dir: String,
+    observer: Arc<dyn ProgressObserver>,
+) -> Result<DownloadReport> {
+    Downloader::new(options, ytdlp_dir)
+        .observer(observer)
+        .run()
+        .await
+}
+
+/// A configurable download run.
+///
+/// [`download_spotify`] and [`download_spotify_with_observer`] cover the
+/// common cases; use this to swap in a different [`MetadataSource`],
+/// [`AudioSource`], [`Transcoder`] or [`ProgressObserver`].
+pub struct Downloader {
+    options: DownloadOptions,
+    ytdlp_dir: String,
+    observer: Arc<dyn ProgressObserver>,
+    metadata_source: Arc<dyn MetadataSource>,
+    audio_source: Option<Arc<dyn AudioSource>>,
+    transcoder: Arc<dyn Transcoder>,
+    output_spec: OutputSpec,
+}
+
+impl Downloader {
+    /// Download `options.url` using Spotify for metadata, YouTube for audio,
+    /// ffmpeg for transcoding and an observer picked from `options.verbosity`.
+    pub fn new(options: DownloadOptions, ytdlp_dir: String) -> Self {
+        let observer: Arc<dyn ProgressObserver> = if options.verbosity == Verbosity::NoBars {
+            Arc::new(LogObserver)
+        } else {
+            Arc::new(IndicatifObserver::new(MultiProgress::new()))
+        };
+        let metadata_source = Arc::new(SpotifySource::from_options(&options));
+        let output_spec = OutputSpec::from_options(&options);
+        Downloader {
+            options,
+            ytdlp_dir,
+            observer,
+            metadata_source,
+            audio_source: None,
+            transcoder: Arc::new(FfmpegTranscoder::new()),
+            output_spec,
         }
-        "debug" => {
-            let mut builder = env_logger::Builder::new();
-            builder.filter_level(LevelFilter::Debug);
-            builder
+    }
+
+    /// Report progress to `observer`.
+    pub fn observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Resolve tracks and tags through `source` instead of Spotify.
+    pub fn metadata_source(mut self, source: Arc<dyn MetadataSource>) -> Self {
+        self.metadata_source = source;
+        self
+    }
+
+    /// Find and fetch audio through `source` instead of YouTube. yt-dlp is
+    /// then never installed, and `ytdlp_dir` is ignored.
+    pub fn audio_source(mut self, source: Arc<dyn AudioSource>) -> Self {
+        self.audio_source = Some(source);
+        self
+    }
+
+    /// Transcode fetched audio with `transcoder` instead of the default
+    /// [`FfmpegTranscoder`].
+    pub fn transcoder(mut self, transcoder: Arc<dyn Transcoder>) -> Self {
+        self.transcoder = transcoder;
+        self
+    }
+
+    /// Encode with `spec` instead of [`OutputSpec::from_options`]. The output
+    /// container still follows `options.format`, but `options.bitrate` is
+    /// ignored.
+    pub fn output_spec(mut self, spec: OutputSpec) -> Self {
+        self.output_spec = spec;
+        self
+    }
+
+    /// Run the download. See [`download_spotify`] for what counts as an error.
+    pub async fn run(self) -> Result<DownloadReport> {
+        let Downloader {
+            options,
+            ytdlp_dir,
+            observer,
+            metadata_source,
+            audio_source,
+            transcoder,
+            output_spec,
+        } = self;
+        let start_time = Instant::now();
+        options.validate()?;
+        let mut plans = Vec::new();
+        for input in options.inputs() {
+            plans.push(resolve_input(input, metadata_source.as_ref(), &options).await?);
         }
-        "none" => {
-            let mut builder = env_logger::Builder::new();
-            builder
-                .format(|buf, record| writeln!(buf, "{}", record.args()))
-                .filter_level(LevelFilter::Off);
-            builder
+        let tracks = merge_plans(plans, options.no_dupes);
+        observer.on_event(&ProgressEvent::Resolved {
+            total: tracks.len(),
+        });
+        let audio_source = match audio_source {
+            Some(source) => source,
+            None => Arc::new(
+                YoutubeSource::new(download_ytdlp(ytdlp_dir)?)
+                    .concurrent_fragments(options.concurrent_downloads),
+            ),
+        };
+        let album_matches = match_whole_albums(&tracks, audio_source.as_ref()).await;
+        let pipeline = Arc::new(Pipeline {
+            options,
+            observer,
+            artwork: ArtworkCache::new(),
+            album_matches,
+            audio_source,
+            transcoder,
+            output_spec,
+        });
+        let mut report = download_and_tag_tracks(tracks, Arc::clone(&pipeline)).await?;
+        let Pipeline {
+            options, observer, ..
+        } = pipeline.as_ref();
+        report.elapsed = start_time.elapsed();
+        observer.on_event(&ProgressEvent::Done {
+            elapsed: report.elapsed,
+        });
+
+        let temp_path = PathBuf::from(format!("{}/temp", options.output_dir));
+        if temp_path.exists() {
+            remove_dir_all(temp_path)?;
         }
-        _ => {
-            let mut builder = env_logger::Builder::new();
-            builder
-                .format(|buf, record| writeln!(buf, "{}", record.args()))
-                .filter_level(LevelFilter::Info)
-                .filter_module("spotify_rs", LevelFilter::Warn)
-                .filter_module("rustypipe_downloader", LevelFilter::Warn);
-            builder
+        match report.write_not_downloaded(Path::new(&options.output_dir)) {
+            Ok(Some(path)) => info!(
+                "{} entries couldn't be downloaded, see {}",
+                report.not_downloadable(),
+                path.display()
+            ),
+            Ok(None) => {}
+            Err(e) => error!("Couldn't write the list of entries not downloaded: {e}"),
         }
-    };
+        Ok(report)
+    }
+}
 
-    if !no_bars {
-        let logger = logger.build();
-        LogWrapper::new(multi.clone(), logger).try_init().unwrap();
-    } else {
-        logger.init();
+/// Plan the tracks behind one input URL, library keyword or import file.
+async fn resolve_input(
+    input: &str,
+    source: &dyn MetadataSource,
+    options: &DownloadOptions,
+) -> Result<Vec<PlannedTrack>> {
+    if is_import_file(input) {
+        return fetch_import(Path::new(input), source, options).await;
     }
+    if let Some(library) = LibraryInput::parse(input) {
+        return match library {
+            LibraryInput::LikedSongs => fetch_liked(source, options).await,
+            LibraryInput::SavedAlbums => fetch_saved_albums(source).await,
+            LibraryInput::Playlists => fetch_user_playlists(source, options).await,
+        };
+    }
+    let url = if is_short_link(input) {
+        let url = resolve_short_link(input).await?;
+        info!("Resolved {input} to {url}");
+        url
+    } else {
+        input.to_string()
+    };
+    let SpotifyResource { kind, id } = SpotifyResource::parse(&url)?;
 
-    let (url_type, id) = is_valid_spotify_url(&options.url).ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid Spotify URL")
-    })?;
+    match kind {
+        ResourceKind::Track => fetch_track(&id, source).await,
+        ResourceKind::Album => fetch_album(&id, source).await,
+        ResourceKind::Playlist => fetch_playlist(&id, source, options).await,
+        ResourceKind::Artist => fetch_artist(&id, source, options).await,
+        ResourceKind::Episode => fetch_episode(&id, source).await,
+        ResourceKind::Show => fetch_show(&id, source).await,
+    }
+}
 
-    let tracks = match url_type {
-        SpotifyUrlType::Track => fetch_track(&id, &options).await?,
-        SpotifyUrlType::Album => fetch_album(&id, &options).await?,
-        SpotifyUrlType::Playlist => fetch_playlist(&id, &options).await?,
-        SpotifyUrlType::Artist => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Artist URLs are not supported. Please provide a track, album, or playlist URL.",
-            )));
+/// Ask `audio_source` for a release per album downloaded whole, and return
+/// the candidates it matched by track ID. Albums that fail to match are left
+/// to per-track search.
+async fn match_whole_albums(
+    tracks: &[PlannedTrack],
+    audio_source: &dyn AudioSource,
+) -> HashMap<String, AudioCandidate> {
+    let mut albums: Vec<(&AlbumInfo, Vec<TrackInfo>)> = Vec::new();
+    for planned in tracks
+        .iter()
+        .filter(|planned| planned.whole_album && planned.track.status.is_downloadable())
+    {
+        let album = &planned.track.album;
+        match albums.iter_mut().find(|(known, _)| known.id == album.id) {
+            Some((_, album_tracks)) => album_tracks.push(planned.track.clone()),
+            None => albums.push((album, vec![planned.track.clone()])),
         }
-    };
-    let final_mult = multi.clone();
-    download_and_tag_tracks(tracks, &options, multi, ytdlp_dir).await?;
-    if options.verbosity != "no-bars" {
-        let bar = final_mult.add(ProgressBar::new(100));
-        bar.set_style(ProgressStyle::with_template("{msg}")?);
-        bar.finish_with_message(format!("Took {}s", start_time.elapsed().as_secs()));
     }
 
-    info!("Took {}s", start_time.elapsed().as_secs());
-    let temp_path = PathBuf::from(format!("{}/temp", options.output_dir));
-    if temp_path.exists() {
-        remove_dir_all(temp_path)?;
+    let mut matches = HashMap::new();
+    for (album, album_tracks) in albums {
+        match audio_source.album_candidates(album, &album_tracks).await {
+            Ok(candidates) => matches.extend(
+                album_tracks
+                    .into_iter()
+                    .zip(candidates)
+                    .filter_map(|(track, candidate)| Some((track.id, candidate?))),
+            ),
+            Err(e) => warn!("Matching the album {} failed: {e}", album.name),
+        }
     }
-    Ok(())
+    matches
+}
+
+/// Everything the per-track tasks share.
+struct Pipeline {
+    options: DownloadOptions,
+    observer: Arc<dyn ProgressObserver>,
+    artwork: ArtworkCache,
+    /// Candidates picked for tracks of albums downloaded whole, by track ID.
+    album_matches: HashMap<String, AudioCandidate>,
+    audio_source: Arc<dyn AudioSource>,
+    transcoder: Arc<dyn Transcoder>,
+    output_spec: OutputSpec,
 }
 
 async fn download_and_tag_tracks(
-    tracks: HashMap<String, Track>,
-    options: &DownloadOptions,
-    multi: MultiProgress,
-    ytdlp_dir: String,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracks: Vec<PlannedTrack>,
+    pipeline: Arc<Pipeline>,
+) -> Result<DownloadReport> {
+    let options = &pipeline.options;
+    let observer = &pipeline.observer;
     let mut handles = Vec::new();
     let semaphore = Arc::new(Semaphore::new(options.concurrent_downloads));
-    let lenght = tracks.clone().len();
-    let options_cloned = Arc::new(DownloadOptions {
-        url: options.url.clone(),
-        client_id: options.client_id.to_string().clone(),
-        client_secret: options.client_secret.to_string().clone(),
-        output_dir: options.output_dir.to_string(),
-        concurrent_downloads: options.concurrent_downloads,
-        no_dupes: options.no_dupes,
-        bitrate: options.bitrate.clone(),
-        format: options.format.clone(),
-        verbosity: options.verbosity.clone(),
-        no_tag: options.no_tag,
-    });
-
-    let ytdlp_path = download_ytdlp(ytdlp_dir)?;
-    let multi = Arc::new(multi);
+    let lenght = tracks.len();
 
-    for (i, (name, track)) in tracks.iter().enumerate() {
+    for (i, planned) in tracks.into_iter().enumerate() {
         let semaphore = semaphore.clone();
-        let name = sanitize_filename(name.as_str());
-        let track = track.clone();
-        let options_cloned = Arc::clone(&options_cloned);
-        let multi = Arc::clone(&multi);
+        let name = planned.relative_name();
+        let track = planned.track;
+        let pipeline = Arc::clone(&pipeline);
+        let output_path = PathBuf::from(format!(
+            "{}/{}.{}",
+            options.output_dir, name, options.format
+        ));
+        let mut report = TrackReport::new(&name, &track.id, output_path);
+        let progress = TrackProgress {
+            index: i,
+            total: lenght,
+            name: name.clone(),
+        };
+        let task_info = (track.id.clone(), progress.clone());
 
-        if options.verbosity.clone() != "no-bars" {
-            let task_ytdlp_path = ytdlp_path.clone();
-            let handle = tokio::spawn(async move {
-                let bar = multi.add(ProgressBar::new_spinner());
-                bar.set_style(ProgressStyle::with_template("{spinner:.cyan} {msg}")?);
-                bar.enable_steady_tick(Duration::from_millis(100));
-
-                let _permit = semaphore.acquire().await.unwrap();
-                bar.set_message(format!("{}/{} Downloading: {}", i + 1, lenght, name));
-                if let DownloadResult::Completed =
-                    search_yt(&name, options_cloned.as_ref(), task_ytdlp_path).await?
-                {
-                    if !options_cloned.no_tag {
-                        bar.set_message(format!("{}/{} Tagging: {}", i + 1, lenght, name));
-                        metadata(&name, &track, options_cloned.as_ref()).await?;
+        let handle = tokio::spawn(async move {
+            let _permit = semaphore.acquire().await.unwrap();
+            let result = process_track(&mut report, &track, &progress, &pipeline).await;
+            let observer = &pipeline.observer;
+            report.outcome = match result {
+                Ok(DownloadResult::NotDownloadable) => {
+                    observer.on_event(&ProgressEvent::NotDownloadable {
+                        track: progress,
+                        reason: track.status.to_string(),
+                    });
+                    TrackOutcome::NotDownloadable(track.status.clone())
+                }
+                Ok(result) => {
+                    observer.on_event(&ProgressEvent::Finished {
+                        track: progress,
+                        skipped: result == DownloadResult::Skipped,
+                    });
+                    match result {
+                        DownloadResult::Skipped => TrackOutcome::Skipped,
+                        _ => TrackOutcome::Completed,
                     }
-                } else {
-                    bar.finish_with_message(format!("File already exists, skipping!: {name}"));
-                    return Ok::<(), Box<dyn std::error::Error + Send + Sync>>(());
                 }
-                bar.finish_with_message(format!("Finished {name}!"));
-
-                Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
-            });
-            handles.push(handle);
-        } else {
-            let task_ytdlp_path = ytdlp_path.clone();
-            let handle = tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.unwrap();
-                info!("{}/{} Starting download: {}", i + 1, lenght, name);
-                if let DownloadResult::Completed =
-                    search_yt(&name, &options_cloned, task_ytdlp_path).await?
-                {
-                    metadata(&name, &track, &options_cloned).await?;
-                    info!("{}/{} Tagging: {}", i + 1, lenght, name);
-                } else {
-                    info!("File already exists, skipping: {name}");
-                    return Ok::<(), Box<dyn std::error::Error + Send + Sync>>(());
+                Err(e) => {
+                    observer.on_event(&ProgressEvent::Failed {
+                        track: progress,
+                        error: e.to_string(),
+                    });
+                    TrackOutcome::Failed(e)
                 }
-                info!("Finished {name}!");
-                Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
-            });
-            handles.push(handle);
-        }
+            };
+            report
+        });
+        handles.push((handle, task_info));
     }
-    for handle in handles {
+
+    let mut report = DownloadReport::default();
+    for (handle, (spotify_id, progress)) in handles {
         match handle.await {
-            Ok(Ok(())) => {}
-            Ok(Err(e)) => error!("Task failed: {e}"),
-            Err(e) => error!("Join error: {e}"),
+            Ok(track_report) => report.tracks.push(track_report),
+            Err(e) => {
+                error!("Join error: {e}");
+                observer.on_event(&ProgressEvent::Failed {
+                    track: progress.clone(),
+                    error: e.to_string(),
+                });
+                let mut track_report =
+                    TrackReport::new(&progress.name, &spotify_id, PathBuf::new());
+                track_report.outcome = TrackOutcome::Failed(Error::TaskFailed(e.to_string()));
+                report.tracks.push(track_report);
+            }
         }
     }
-    if PathBuf::from(format!("{}/temp", options.output_dir)).exists() {
-        fs::remove_dir_all(format!("{}/temp", options.output_dir))?;
+    info!(
+        "Finished! {} completed, {} skipped, {} not downloadable, {} failed",
+        report.completed(),
+        report.skipped(),
+        report.not_downloadable(),
+        report.failed()
+    );
+
+    Ok(report)
+}
+
+/// Run search, download, transcode and tagging for one track, recording the
+/// chosen candidate and stage timings in `report` and emitting progress events.
+async fn process_track(
+    report: &mut TrackReport,
+    track: &TrackInfo,
+    progress: &TrackProgress,
+    pipeline: &Pipeline,
+) -> Result<DownloadResult> {
+    let Pipeline {
+        options,
+        observer,
+        artwork,
+        album_matches,
+        audio_source,
+        transcoder,
+        output_spec,
+    } = pipeline;
+    if !track.status.is_downloadable() {
+        return Ok(DownloadResult::NotDownloadable);
+    }
+    let name = report.name.clone();
+    let dest = PathBuf::from(format!("{}/temp/{}", options.output_dir, name));
+    for path in [&dest, &report.output_path] {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    if report.output_path.exists() {
+        return Ok(DownloadResult::Skipped);
+    }
+
+    observer.on_event(&ProgressEvent::Searching {
+        track: progress.clone(),
+    });
+    let started = Instant::now();
+    let candidate = match album_matches.get(&track.id) {
+        Some(candidate) => Ok(candidate.clone()),
+        None => audio_source.candidates(track).await.and_then(|candidates| {
+            candidates
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::NoResults(track.display_name()))
+        }),
     };
+    report.timings.search = Some(started.elapsed());
+    let candidate = candidate?;
+    report.youtube_id = Some(candidate.id.clone());
+    report.match_method = Some(candidate.method);
+    observer.on_event(&ProgressEvent::Matched {
+        track: progress.clone(),
+        youtube_id: candidate.id.clone(),
+    });
 
-    info!("Finished!");
+    let started = Instant::now();
+    let fetched = match audio_source
+        .fetch(&candidate, &dest, progress, observer.as_ref())
+        .await
+    {
+        Ok(file) => {
+            observer.on_event(&ProgressEvent::Transcoding {
+                track: progress.clone(),
+            });
+            transcoder
+                .transcode(&file, &report.output_path, output_spec)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    report.timings.download = Some(started.elapsed());
+    fetched?;
+    info!("Completed: {name}");
 
-    Ok(())
+    if !options.no_tag {
+        observer.on_event(&ProgressEvent::Tagging {
+            track: progress.clone(),
+        });
+        let started = Instant::now();
+        let tagged = metadata(&name, track, options, artwork).await;
+        report.timings.tagging = Some(started.elapsed());
+        tagged?;
+    }
+    Ok(DownloadResult::Completed)
 }
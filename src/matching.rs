@@ -0,0 +1,624 @@
+//! Scoring audio candidates against the catalog track they should match.
+//!
+//! Search results often lead with covers, live versions, extended mixes or a
+//! different artist's song of the same name. [`score`] rates a candidate by:
+//! - duration: full marks within 2 seconds, nothing from 30 seconds off
+//! - title and artist: fuzzy similarity, ignoring case, punctuation,
+//!   bracketed parts and `feat.` credits
+//! - album: similarity of the album names, when the candidate has one
+//! - a penalty per version marker (`live`, `cover`, `remix`, `sped up`, ...)
+//!   in the candidate that the catalog title and album don't have
+//!
+//! [`rank`] orders candidates best first and logs every score at debug level.
+//! The result of an ISRC lookup is checked by [`isrc_match`].
+//! [`match_album`] maps a whole album onto one release.
+//!
+//! Example
+//! ```
+//! use std::time::Duration;
+//! use rustifydl::audio::AudioCandidate;
+//! use rustifydl::matching::rank;
+//! use rustifydl::TrackInfo;
+//!
+//! let track = TrackInfo {
+//!     name: "One More Time".into(),
+//!     artists: vec!["Daft Punk".into()],
+//!     duration_ms: 320_357,
+//!     ..TrackInfo::default()
+//! };
+//! let candidate = |id: &str, title: &str, secs: u64| AudioCandidate {
+//!     id: id.into(),
+//!     title: title.into(),
+//!     artists: vec!["Daft Punk".into()],
+//!     duration: Some(Duration::from_secs(secs)),
+//!     ..AudioCandidate::default()
+//! };
+//! let ranked = rank(
+//!     &track,
+//!     vec![
+//!         candidate("live", "One More Time (Live)", 344),
+//!         candidate("album", "One More Time", 321),
+//!     ],
+//! );
+//! assert_eq!(ranked[0].0.id, "album");
+//! assert!(ranked[0].1.total > ranked[1].1.total);
+//! ```
+
+use std::fmt;
+
+use log::debug;
+use strsim::sorensen_dice;
+
+use crate::{
+    audio::AudioCandidate,
+    source::{AlbumInfo, TrackInfo},
+};
+
+const DURATION_WEIGHT: f64 = 0.35;
+const TITLE_WEIGHT: f64 = 0.3;
+const ARTIST_WEIGHT: f64 = 0.25;
+const ALBUM_WEIGHT: f64 = 0.1;
+
+/// Deviation in seconds that still counts as the same recording.
+const DURATION_TOLERANCE: f64 = 2.0;
+/// Deviation in seconds from which duration adds nothing.
+const DURATION_LIMIT: f64 = 30.0;
+/// Deviation in seconds up to which an ISRC result is taken as is.
+const ISRC_DURATION_TOLERANCE: f64 = 5.0;
+/// Title and artist similarity an ISRC result needs to be taken.
+const ISRC_TITLE_THRESHOLD: f64 = 0.6;
+const ISRC_ARTIST_THRESHOLD: f64 = 0.5;
+/// Deviation per track in seconds up to which a release's total duration
+/// matches the album's.
+const ALBUM_DURATION_TOLERANCE: f64 = 3.0;
+/// Title similarity from which a release track at another position is
+/// taken for a track.
+const ALBUM_TITLE_THRESHOLD: f64 = 0.8;
+
+/// Markers of a different version of the song, and the penalty for each.
+const VERSION_MARKERS: [&str; 9] = [
+    "live",
+    "cover",
+    "remix",
+    "sped up",
+    "slowed",
+    "nightcore",
+    "karaoke",
+    "instrumental",
+    "extended",
+];
+const VERSION_PENALTY: f64 = 0.3;
+
+/// How well a candidate matches a track. Each part is between 0 and 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MatchScore {
+    /// Weighted sum of the parts, minus the penalty. Higher is better.
+    pub total: f64,
+    /// Closeness of the durations; 0.5 when either duration is unknown.
+    pub duration: f64,
+    /// Title similarity.
+    pub title: f64,
+    /// Similarity of the best-matching pair of artist names.
+    pub artist: f64,
+    /// Album name similarity; 0 when the candidate has no album.
+    pub album: f64,
+    /// Deducted for version markers the track doesn't have.
+    pub penalty: f64,
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} (duration {:.2}, title {:.2}, artist {:.2}, album {:.2}, penalty {:.2})",
+            self.total, self.duration, self.title, self.artist, self.album, self.penalty
+        )
+    }
+}
+
+/// Score `candidate` as a match for `track`.
+pub fn score(track: &TrackInfo, candidate: &AudioCandidate) -> MatchScore {
+    let duration = match candidate.duration {
+        Some(duration) if track.duration_ms > 0 => {
+            let delta = (duration.as_secs_f64() - f64::from(track.duration_ms) / 1000.0).abs();
+            (1.0 - (delta - DURATION_TOLERANCE) / (DURATION_LIMIT - DURATION_TOLERANCE))
+                .clamp(0.0, 1.0)
+        }
+        _ => 0.5,
+    };
+
+    let candidate_title = normalize(&candidate.title);
+    // Catalog titles put the version after a dash: "Song - 2011 Remaster".
+    let title = [Some(track.name.as_str()), track.name.split(" - ").next()]
+        .into_iter()
+        .flatten()
+        .map(|name| similarity(&normalize(name), &candidate_title))
+        .fold(0.0, f64::max);
+
+    let artist = track
+        .artists
+        .iter()
+        .flat_map(|wanted| {
+            candidate
+                .artists
+                .iter()
+                .map(move |found| similarity(&normalize(wanted), &normalize(found)))
+        })
+        .fold(0.0, f64::max);
+
+    let album = candidate.album.as_deref().map_or(0.0, |album| {
+        similarity(&normalize(&track.album.name), &normalize(album))
+    });
+
+    let wanted = words(&format!("{} {}", track.name, track.album.name));
+    let found = words(&format!(
+        "{} {}",
+        candidate.title,
+        candidate.album.as_deref().unwrap_or_default()
+    ));
+    let penalty = VERSION_MARKERS
+        .iter()
+        .filter(|marker| {
+            let marker = format!(" {marker} ");
+            found.contains(&marker) && !wanted.contains(&marker)
+        })
+        .count() as f64
+        * VERSION_PENALTY;
+
+    MatchScore {
+        total: DURATION_WEIGHT * duration
+            + TITLE_WEIGHT * title
+            + ARTIST_WEIGHT * artist
+            + ALBUM_WEIGHT * album
+            - penalty,
+        duration,
+        title,
+        artist,
+        album,
+        penalty,
+    }
+}
+
+/// Whether `candidate` is as long as `track`, give or take a few seconds.
+/// Candidates without a duration never agree.
+pub fn duration_agrees(track: &TrackInfo, candidate: &AudioCandidate) -> bool {
+    candidate.duration.is_some_and(|duration| {
+        (duration.as_secs_f64() - f64::from(track.duration_ms) / 1000.0).abs()
+            <= ISRC_DURATION_TOLERANCE
+    })
+}
+
+/// The top result of an ISRC search, if it is `track`: as long, give or
+/// take a few seconds, with a similar title and artist.
+///
+/// YouTube Music doesn't index most ISRCs and answers with loosely related
+/// songs instead, many of them about as long; so only the top result counts,
+/// and the duration alone isn't enough.
+pub fn isrc_match(track: &TrackInfo, results: Vec<AudioCandidate>) -> Option<AudioCandidate> {
+    let top = results.into_iter().next()?;
+    let score = score(track, &top);
+    (duration_agrees(track, &top)
+        && score.title >= ISRC_TITLE_THRESHOLD
+        && score.artist >= ISRC_ARTIST_THRESHOLD)
+        .then_some(top)
+}
+
+/// Map the `tracks` of `album` onto the tracks of a `release`, in release
+/// order, or `None` if the release isn't the same album.
+///
+/// The release must have [`total_tracks`](AlbumInfo::total_tracks) tracks,
+/// and those at the positions of `tracks` must add up to about the same
+/// length. Each track then gets the release track at its position if the
+/// durations agree, else an unused one of the same length and title. Tracks
+/// left without one get `None`, for a search of their own.
+pub fn match_album(
+    album: &AlbumInfo,
+    tracks: &[TrackInfo],
+    release: &[AudioCandidate],
+) -> Option<Vec<Option<AudioCandidate>>> {
+    if tracks.is_empty() || release.len() != album.total_tracks as usize {
+        return None;
+    }
+    let positions = album_positions(album, tracks)?;
+
+    let wanted: f64 = tracks
+        .iter()
+        .map(|track| f64::from(track.duration_ms) / 1000.0)
+        .sum();
+    let found: f64 = positions
+        .iter()
+        .map(|&position| release.get(position)?.duration.map(|d| d.as_secs_f64()))
+        .sum::<Option<f64>>()?;
+    if (wanted - found).abs() > ALBUM_DURATION_TOLERANCE * tracks.len() as f64 {
+        return None;
+    }
+
+    let mut used = vec![false; release.len()];
+    let mut matches = vec![None; tracks.len()];
+    // Positions first, so a track can't take another's place.
+    for (i, (track, &position)) in tracks.iter().zip(&positions).enumerate() {
+        if duration_agrees(track, &release[position]) && !used[position] {
+            used[position] = true;
+            matches[i] = Some(release[position].clone());
+        }
+    }
+    for (i, track) in tracks.iter().enumerate() {
+        if matches[i].is_some() {
+            continue;
+        }
+        let title = normalize(&track.name);
+        let other = release.iter().enumerate().position(|(j, candidate)| {
+            !used[j]
+                && duration_agrees(track, candidate)
+                && similarity(&title, &normalize(&candidate.title)) >= ALBUM_TITLE_THRESHOLD
+        });
+        if let Some(j) = other {
+            used[j] = true;
+            matches[i] = Some(release[j].clone());
+        }
+    }
+    Some(matches)
+}
+
+/// Zero-based position of each track in the album's flat track list. With
+/// every track present, that's their disc and track order; otherwise only
+/// single-disc albums can be placed, by track number.
+fn album_positions(album: &AlbumInfo, tracks: &[TrackInfo]) -> Option<Vec<usize>> {
+    if tracks.len() == album.total_tracks as usize {
+        let mut order: Vec<usize> = (0..tracks.len()).collect();
+        order.sort_by_key(|&i| (tracks[i].disc_number, tracks[i].track_number));
+        let mut positions = vec![0; tracks.len()];
+        for (position, i) in order.into_iter().enumerate() {
+            positions[i] = position;
+        }
+        return Some(positions);
+    }
+    tracks
+        .iter()
+        .map(|track| {
+            (track.disc_number <= 1 && track.track_number >= 1)
+                .then(|| track.track_number as usize - 1)
+                .filter(|&position| position < album.total_tracks as usize)
+        })
+        .collect()
+}
+
+/// Score every candidate and sort them best first. Ties keep the order the
+/// source returned them in.
+pub fn rank(
+    track: &TrackInfo,
+    candidates: Vec<AudioCandidate>,
+) -> Vec<(AudioCandidate, MatchScore)> {
+    let mut ranked: Vec<(AudioCandidate, MatchScore)> = candidates
+        .into_iter()
+        .map(|candidate| {
+            let score = score(track, &candidate);
+            (candidate, score)
+        })
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| b.total.total_cmp(&a.total));
+    for (candidate, score) in &ranked {
+        debug!(
+            "{}: {} \"{}\" by {}: {score}",
+            track.display_name(),
+            candidate.id,
+            candidate.title,
+            candidate.artists.join(", ")
+        );
+    }
+    ranked
+}
+
+/// Lowercase words of `text` separated by single spaces, with a space at
+/// either end so whole words can be found with `contains(" word ")`.
+fn words(text: &str) -> String {
+    let text: String = text
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    format!(
+        " {} ",
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    )
+}
+
+/// `text` reduced to what identifies a song: lowercase words without
+/// bracketed parts or a trailing `feat.`/`ft.` credit.
+fn normalize(text: &str) -> String {
+    let mut depth = 0usize;
+    let unbracketed: String = text
+        .chars()
+        .filter(|&c| {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => return depth == 0,
+            }
+            false
+        })
+        .collect();
+    let words = words(&unbracketed);
+    let words = [" feat ", " ft ", " featuring "]
+        .iter()
+        .filter_map(|credit| words.find(credit))
+        .min()
+        .map_or(words.as_str(), |end| &words[..end]);
+    words.trim().to_string()
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    sorensen_dice(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn track(name: &str, album: &str, secs: u32) -> TrackInfo {
+        let mut track = TrackInfo {
+            name: name.to_string(),
+            artists: vec!["Artist".to_string()],
+            duration_ms: secs * 1000,
+            ..TrackInfo::default()
+        };
+        track.album.name = album.to_string();
+        track
+    }
+
+    fn candidate(title: &str, secs: Option<u64>) -> AudioCandidate {
+        AudioCandidate {
+            id: title.to_string(),
+            title: title.to_string(),
+            artists: vec!["Artist".to_string()],
+            duration: secs.map(Duration::from_secs),
+            ..AudioCandidate::default()
+        }
+    }
+
+    #[test]
+    fn version_markers_are_penalized() {
+        let song = track("Song", "Album", 200);
+        assert_eq!(score(&song, &candidate("Song", Some(200))).penalty, 0.0);
+        assert_eq!(
+            score(&song, &candidate("Song (Live)", Some(200))).penalty,
+            VERSION_PENALTY
+        );
+        assert_eq!(
+            score(&song, &candidate("Song [Sped Up Remix]", Some(200))).penalty,
+            2.0 * VERSION_PENALTY
+        );
+        // Only whole words count.
+        assert_eq!(score(&song, &candidate("Alive", Some(200))).penalty, 0.0);
+    }
+
+    #[test]
+    fn version_markers_in_the_catalog_are_not_penalized() {
+        let candidate = candidate("Song (Live)", Some(200));
+        assert_eq!(
+            score(&track("Song - Live at Wembley", "Album", 200), &candidate).penalty,
+            0.0
+        );
+        assert_eq!(
+            score(&track("Song", "Live in Paris", 200), &candidate).penalty,
+            0.0
+        );
+        let mut with_album = candidate.clone();
+        with_album.title = "Song".to_string();
+        with_album.album = Some("Live in Paris".to_string());
+        assert_eq!(
+            score(&track("Song", "Album", 200), &with_album).penalty,
+            VERSION_PENALTY
+        );
+    }
+
+    #[test]
+    fn missing_durations_score_as_unknown() {
+        let song = track("Song", "Album", 200);
+        let undated = candidate("Song", None);
+        assert_eq!(score(&song, &undated).duration, 0.5);
+        assert!(!duration_agrees(&song, &undated));
+        assert_eq!(
+            score(&track("Song", "Album", 0), &candidate("Song", Some(30))).duration,
+            0.5
+        );
+        assert_eq!(score(&song, &candidate("Song", Some(201))).duration, 1.0);
+        assert_eq!(score(&song, &candidate("Song", Some(240))).duration, 0.0);
+
+        let ranked = rank(
+            &song,
+            vec![
+                undated,
+                candidate("Song", Some(200)),
+                candidate("Song", Some(260)),
+            ],
+        );
+        let order: Vec<_> = ranked
+            .iter()
+            .map(|(candidate, _)| candidate.duration)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                Some(Duration::from_secs(200)),
+                None,
+                Some(Duration::from_secs(260))
+            ]
+        );
+    }
+
+    #[test]
+    fn feat_credits_and_brackets_are_stripped() {
+        assert_eq!(normalize("Song (feat. Someone)"), "song");
+        assert_eq!(normalize("Song feat. Someone"), "song");
+        assert_eq!(normalize("Song ft. Someone & Other"), "song");
+        assert_eq!(normalize("Song Featuring Someone"), "song");
+        assert_eq!(normalize("Feather"), "feather");
+        assert_eq!(
+            score(
+                &track("Song", "", 200),
+                &candidate("Song feat. Someone", Some(200))
+            )
+            .title,
+            1.0
+        );
+    }
+
+    fn numbered(disc: u32, number: u32) -> TrackInfo {
+        TrackInfo {
+            disc_number: disc,
+            track_number: number,
+            ..TrackInfo::default()
+        }
+    }
+
+    /// A three-track album and the tracks of it that are wanted.
+    fn album_of(wanted: &[(u32, &str, u32)]) -> (AlbumInfo, Vec<TrackInfo>) {
+        let album = AlbumInfo {
+            name: "Album".to_string(),
+            total_tracks: 3,
+            ..AlbumInfo::default()
+        };
+        let tracks = wanted
+            .iter()
+            .map(|&(number, name, secs)| TrackInfo {
+                disc_number: 1,
+                track_number: number,
+                ..track(name, "Album", secs)
+            })
+            .collect();
+        (album, tracks)
+    }
+
+    fn ids(matches: &[Option<AudioCandidate>]) -> Vec<Option<&str>> {
+        matches
+            .iter()
+            .map(|candidate| candidate.as_ref().map(|c| c.id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn album_positions_follow_disc_and_track_order() {
+        let album = AlbumInfo {
+            total_tracks: 4,
+            ..AlbumInfo::default()
+        };
+        let tracks = [
+            numbered(2, 1),
+            numbered(1, 1),
+            numbered(2, 2),
+            numbered(1, 2),
+        ];
+        assert_eq!(album_positions(&album, &tracks), Some(vec![2, 0, 3, 1]));
+    }
+
+    #[test]
+    fn album_positions_of_partial_albums() {
+        let album = AlbumInfo {
+            total_tracks: 10,
+            ..AlbumInfo::default()
+        };
+        assert_eq!(
+            album_positions(&album, &[numbered(1, 3), numbered(1, 7)]),
+            Some(vec![2, 6])
+        );
+        // Discs after the first can't be placed without the tracks before them.
+        assert_eq!(
+            album_positions(&album, &[numbered(1, 3), numbered(2, 1)]),
+            None
+        );
+        assert_eq!(album_positions(&album, &[numbered(1, 11)]), None);
+        assert_eq!(album_positions(&album, &[numbered(1, 0)]), None);
+    }
+
+    #[test]
+    fn album_needs_the_same_track_count() {
+        let (album, tracks) = album_of(&[(1, "One", 200), (2, "Two", 180), (3, "Three", 240)]);
+        let release = [
+            candidate("One", Some(200)),
+            candidate("Two", Some(180)),
+            candidate("Three", Some(240)),
+        ];
+        assert_eq!(
+            ids(&match_album(&album, &tracks, &release).unwrap()),
+            [Some("One"), Some("Two"), Some("Three")]
+        );
+        assert_eq!(match_album(&album, &tracks, &release[..2]), None);
+        let mut bonus = release.to_vec();
+        bonus.push(candidate("Bonus", Some(200)));
+        assert_eq!(match_album(&album, &tracks, &bonus), None);
+    }
+
+    #[test]
+    fn album_total_duration_must_agree() {
+        let (album, tracks) = album_of(&[(1, "One", 200), (2, "Two", 180), (3, "Three", 240)]);
+        // 3 seconds per track may be off in total.
+        let close = [
+            candidate("One", Some(203)),
+            candidate("Two", Some(183)),
+            candidate("Three", Some(243)),
+        ];
+        assert!(match_album(&album, &tracks, &close).is_some());
+        let off = [
+            candidate("One", Some(204)),
+            candidate("Two", Some(184)),
+            candidate("Three", Some(243)),
+        ];
+        assert_eq!(match_album(&album, &tracks, &off), None);
+        let undated = [
+            candidate("One", Some(200)),
+            candidate("Two", None),
+            candidate("Three", Some(240)),
+        ];
+        assert_eq!(match_album(&album, &tracks, &undated), None);
+    }
+
+    #[test]
+    fn album_tracks_out_of_position_are_found_by_title() {
+        // Two and Three are swapped on the release: their positions fail on
+        // duration, the title fallback finds the right track.
+        let (album, tracks) = album_of(&[(1, "One", 200), (2, "Two", 180), (3, "Three", 240)]);
+        let release = [
+            candidate("One", Some(200)),
+            candidate("Three", Some(240)),
+            candidate("Two", Some(180)),
+        ];
+        let matches = match_album(&album, &tracks, &release).unwrap();
+        assert_eq!(ids(&matches), [Some("One"), Some("Two"), Some("Three")]);
+
+        // Tracks with no agreeing counterpart are left for their own search.
+        let (album, tracks) = album_of(&[(1, "One", 200), (3, "Three", 240)]);
+        let release = [
+            candidate("One", Some(206)),
+            candidate("Two", Some(180)),
+            candidate("Three", Some(234)),
+        ];
+        let matches = match_album(&album, &tracks, &release).unwrap();
+        assert_eq!(ids(&matches), [None, None]);
+    }
+
+    #[test]
+    fn isrc_results_need_a_matching_title_and_artist() {
+        let song = track("Song", "Album", 200);
+        let right = candidate("Song", Some(202));
+        assert_eq!(
+            isrc_match(&song, vec![right.clone()]).map(|c| c.id),
+            Some("Song".to_string())
+        );
+        // Unknown ISRCs get unrelated songs of about the same length.
+        let wrong = candidate("Something Else Entirely", Some(200));
+        assert_eq!(isrc_match(&song, vec![wrong.clone()]), None);
+        let mut other_artist = right.clone();
+        other_artist.artists = vec!["Nobody Related".to_string()];
+        assert_eq!(isrc_match(&song, vec![other_artist]), None);
+        // Only the top result counts.
+        assert_eq!(isrc_match(&song, vec![wrong, right]), None);
+        assert_eq!(isrc_match(&song, vec![candidate("Song", Some(230))]), None);
+        assert_eq!(isrc_match(&song, Vec::new()), None);
+    }
+}
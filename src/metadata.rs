@@ -9,20 +9,60 @@
 //! - WAV: Where supported, write RIFF INFO.
 //!
 //! Artwork is embedded as the front cover when the container allows it.
+//! Everything else comes from the resolved [`TrackInfo`], so tagging makes no
+//! API calls; covers are fetched once per album through an [`ArtworkCache`].
 
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use lofty::{
     config::WriteOptions,
     file::{AudioFile, TaggedFileExt},
     picture::{MimeType, Picture, PictureType},
     read_from_path,
-    tag::{Accessor, Tag},
+    tag::{Accessor, ItemKey, Tag},
 };
 use reqwest;
-use spotify_rs::{ClientCredsClient, model::track::Track};
+use tokio::sync::Mutex;
 
-use crate::DownloadOptions;
+use crate::{AudioFormat, DownloadOptions, Error, Result, source::TrackInfo};
+
+/// Cover images already downloaded in this run, by URL.
+///
+/// Shared by the download tasks, so the tracks of an album fetch its cover once.
+#[derive(Default)]
+pub struct ArtworkCache {
+    images: Mutex<HashMap<String, Arc<Vec<u8>>>>,
+}
+
+impl ArtworkCache {
+    /// An empty cache.
+    pub fn new() -> Self {
+        ArtworkCache::default()
+    }
+
+    /// The image at `url`, downloading it on first use.
+    pub async fn get(&self, url: &str) -> Result<Arc<Vec<u8>>> {
+        if let Some(image) = self.images.lock().await.get(url) {
+            return Ok(Arc::clone(image));
+        }
+        // Not held across the download, so other covers aren't held up.
+        let image = Arc::new(
+            reqwest::get(url)
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(Error::Artwork)?
+                .bytes()
+                .await
+                .map_err(Error::Artwork)?
+                .to_vec(),
+        );
+        self.images
+            .lock()
+            .await
+            .insert(url.to_string(), Arc::clone(&image));
+        Ok(image)
+    }
+}
 
 /// Try to detect the image MIME type from raw bytes.
 ///
@@ -46,25 +86,25 @@ fn detect_image_mime_type(bytes: &[u8]) -> MimeType {
 /// Write metadata tags and artwork to the given song file.
 ///
 /// Behavior:
-/// - Fetches any missing context (e.g., album) from Spotify.
+/// - Takes album details (genres, label, total tracks, release date) from
+///   `track.album`, as filled in by the metadata source.
 /// - Builds a fresh tag and saves using the native container format.
-/// - Embeds front cover artwork and sets artist/album/track/disc/genre/year.
+/// - Embeds front cover artwork and sets artist/album/track/disc/genre/year/label.
+/// - Podcast episodes are tagged with the show as album, the publisher as
+///   artist, the description as comment, the `Podcast` genre and the full
+///   release date.
 ///
-/// Returns an error if the file cannot be tagged or network requests fail.
+/// Returns [`Error::Tagging`] if the file cannot be tagged, or
+/// [`Error::Artwork`] if the cover can't be fetched.
 pub async fn metadata(
     song: &String,
-    track: &Track,
+    track: &TrackInfo,
     options: &DownloadOptions,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let spotify =
-        ClientCredsClient::authenticate(options.client_id.clone(), options.client_secret.clone())
-            .await?;
-
+    artwork: &ArtworkCache,
+) -> Result<()> {
     let path = PathBuf::from(format!(
         "{}/{}.{}",
-        options.output_dir,
-        song,
-        options.format.clone()
+        options.output_dir, song, options.format
     ));
     let mut tagged_file = read_from_path(&path)?;
 
@@ -72,68 +112,50 @@ pub async fn metadata(
     let mut tag = Tag::new(tag_type);
 
     tag.set_title(track.name.clone());
-    tag.set_artist(
-        track
-            .artists
-            .iter()
-            .map(|artist| artist.name.as_str())
-            .collect::<Vec<_>>()
-            .join(", "),
-    );
+    tag.set_artist(track.artist_names());
     tag.set_album(track.album.name.clone());
-    let album = spotify_rs::album(track.album.id.clone())
-        .get(&spotify)
-        .await?;
-    if !album.genres.is_empty() {
-        tag.set_genre(
-            album
-                .genres
-                .iter()
-                .map(|genre| genre.as_str())
-                .collect::<Vec<_>>()
-                .join(", "),
-        );
-    } else {
-        let song_artist = spotify_rs::get_artist(&album.artists[0].id, &spotify).await?;
-        tag.set_genre(
-            song_artist
-                .genres
-                .iter()
-                .map(|genre| genre.as_str())
-                .collect::<Vec<_>>()
-                .join(", "),
-        );
+    let album = &track.album;
+    tag.set_genre(album.genres.join(", "));
+    if let Some(label) = &album.label {
+        tag.insert_text(ItemKey::Label, label.clone());
+    }
+    if let Some(description) = &track.description {
+        tag.set_comment(description.clone());
     }
 
-    tag.set_disk(track.disc_number);
-
-    let image_url = &album.images[0].url;
-    let image_bytes = reqwest::get(image_url).await?.bytes().await?.to_vec();
+    if let Some(image_url) = &album.cover_url {
+        let image_bytes = artwork.get(image_url).await?.to_vec();
 
-    let mime_type = detect_image_mime_type(&image_bytes);
+        let mime_type = detect_image_mime_type(&image_bytes);
 
-    let front_cover = Picture::new_unchecked(
-        PictureType::CoverFront,
-        Some(mime_type),
-        Some("Cover".to_string()),
-        image_bytes,
-    );
-    tag.push_picture(front_cover);
-    tag.set_track(track.track_number);
-    tag.set_track_total(album.total_tracks);
-    tag.set_year(album.release_date[..4].parse::<u32>().unwrap_or(0));
+        let front_cover = Picture::new_unchecked(
+            PictureType::CoverFront,
+            Some(mime_type),
+            Some("Cover".to_string()),
+            image_bytes,
+        );
+        tag.push_picture(front_cover);
+    }
+    if !track.is_episode() {
+        tag.set_disk(track.disc_number);
+        tag.set_track(track.track_number);
+        tag.set_track_total(album.total_tracks);
+    }
+    tag.set_year(album.year().unwrap_or(0));
+    if track.is_episode() && !album.release_date.is_empty() {
+        // Episodes come out daily or weekly, so the full date matters.
+        tag.insert_text(ItemKey::RecordingDate, album.release_date.clone());
+    }
 
     tagged_file.insert_tag(tag);
 
     // Use ID3v2.3 only for MP3; otherwise rely on native tags.
     let mut write_options = WriteOptions::new().remove_others(true);
-    if options.format.eq_ignore_ascii_case("mp3") {
+    if options.format == AudioFormat::Mp3 {
         write_options = write_options.use_id3v23(true);
     }
 
-    tagged_file
-        .save_to_path(path.clone(), write_options)
-        .expect("ERROR: Failed to write the tag!");
+    tagged_file.save_to_path(path.clone(), write_options)?;
 
     Ok(())
 }
@@ -0,0 +1,649 @@
+//! Download options and the typed values they accept.
+//!
+//! [`DownloadOptions`] can be built field by field, through
+//! [`DownloadOptions::builder`], or deserialized from a config file. Either
+//! way [`DownloadOptions::validate`] runs before any network work starts.
+//!
+//! Example
+//! ```
+//! use rustifydl::{AudioFormat, Bitrate, DownloadOptions};
+//!
+//! let opts = DownloadOptions::builder("https://open.spotify.com/album/xxxxxxxx")
+//!     .client_credentials("<client_id>", "<client_secret>")
+//!     .format(AudioFormat::Opus)
+//!     .bitrate(Bitrate::K160)
+//!     .concurrent_downloads(4)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(opts.format.extension(), "opus");
+//!
+//! assert!(DownloadOptions::builder("https://open.spotify.com/album/xxxxxxxx")
+//!     .client_credentials("<client_id>", "<client_secret>")
+//!     .concurrent_downloads(0)
+//!     .build()
+//!     .is_err());
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+/// Output container/codec, also used as the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Flac,
+    Ogg,
+    Opus,
+    M4a,
+    Wav,
+}
+
+impl AudioFormat {
+    /// Every supported format, in CLI order.
+    pub const ALL: [AudioFormat; 6] = [
+        AudioFormat::Mp3,
+        AudioFormat::Flac,
+        AudioFormat::Ogg,
+        AudioFormat::Opus,
+        AudioFormat::M4a,
+        AudioFormat::Wav,
+    ];
+
+    /// File extension without the dot, e.g. `"mp3"`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Opus => "opus",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Wav => "wav",
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for AudioFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        AudioFormat::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::InvalidOptions(format!("Unknown audio format: {s}")))
+    }
+}
+
+/// Target audio bitrate passed to ffmpeg as `-b:a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Bitrate {
+    #[serde(rename = "8k")]
+    K8,
+    #[serde(rename = "16k")]
+    K16,
+    #[serde(rename = "24k")]
+    K24,
+    #[serde(rename = "32k")]
+    K32,
+    #[serde(rename = "40k")]
+    K40,
+    #[serde(rename = "48k")]
+    K48,
+    #[serde(rename = "64k")]
+    K64,
+    #[serde(rename = "80k")]
+    K80,
+    #[serde(rename = "96k")]
+    K96,
+    #[serde(rename = "112k")]
+    K112,
+    #[serde(rename = "128k")]
+    K128,
+    #[serde(rename = "160k")]
+    K160,
+    #[default]
+    #[serde(rename = "192k")]
+    K192,
+    #[serde(rename = "224k")]
+    K224,
+    #[serde(rename = "256k")]
+    K256,
+    #[serde(rename = "320k")]
+    K320,
+}
+
+impl Bitrate {
+    /// Every supported bitrate, lowest first.
+    pub const ALL: [Bitrate; 16] = [
+        Bitrate::K8,
+        Bitrate::K16,
+        Bitrate::K24,
+        Bitrate::K32,
+        Bitrate::K40,
+        Bitrate::K48,
+        Bitrate::K64,
+        Bitrate::K80,
+        Bitrate::K96,
+        Bitrate::K112,
+        Bitrate::K128,
+        Bitrate::K160,
+        Bitrate::K192,
+        Bitrate::K224,
+        Bitrate::K256,
+        Bitrate::K320,
+    ];
+
+    /// Bitrate in kbit/s.
+    pub fn kbps(&self) -> u32 {
+        match self {
+            Bitrate::K8 => 8,
+            Bitrate::K16 => 16,
+            Bitrate::K24 => 24,
+            Bitrate::K32 => 32,
+            Bitrate::K40 => 40,
+            Bitrate::K48 => 48,
+            Bitrate::K64 => 64,
+            Bitrate::K80 => 80,
+            Bitrate::K96 => 96,
+            Bitrate::K112 => 112,
+            Bitrate::K128 => 128,
+            Bitrate::K160 => 160,
+            Bitrate::K192 => 192,
+            Bitrate::K224 => 224,
+            Bitrate::K256 => 256,
+            Bitrate::K320 => 320,
+        }
+    }
+}
+
+impl fmt::Display for Bitrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}k", self.kbps())
+    }
+}
+
+impl FromStr for Bitrate {
+    type Err = Error;
+
+    /// Accepts `"192k"` as well as a bare `"192"`.
+    fn from_str(s: &str) -> Result<Self> {
+        let kbps = s.trim().trim_end_matches(['k', 'K']);
+        Bitrate::ALL
+            .into_iter()
+            .find(|bitrate| bitrate.kbps().to_string() == kbps)
+            .ok_or_else(|| Error::InvalidOptions(format!("Unsupported bitrate: {s}")))
+    }
+}
+
+/// How much the run prints. See [`DownloadOptions::verbosity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verbosity {
+    #[default]
+    Info,
+    Debug,
+    Error,
+    None,
+    Full,
+    NoBars,
+}
+
+impl Verbosity {
+    /// Every verbosity level, in CLI order.
+    pub const ALL: [Verbosity; 6] = [
+        Verbosity::Info,
+        Verbosity::Debug,
+        Verbosity::Error,
+        Verbosity::None,
+        Verbosity::Full,
+        Verbosity::NoBars,
+    ];
+
+    /// Name used on the command line, e.g. `"no-bars"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Info => "info",
+            Verbosity::Debug => "debug",
+            Verbosity::Error => "error",
+            Verbosity::None => "none",
+            Verbosity::Full => "full",
+            Verbosity::NoBars => "no-bars",
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Verbosity::ALL
+            .into_iter()
+            .find(|verbosity| verbosity.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::InvalidOptions(format!("Unknown verbosity: {s}")))
+    }
+}
+
+/// Kind of release, used to filter an artist's discography.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseType {
+    Album,
+    Single,
+    Compilation,
+    /// Releases by other artists the artist is featured on.
+    AppearsOn,
+}
+
+impl ReleaseType {
+    /// Every release type, in the order Spotify lists them.
+    pub const ALL: [ReleaseType; 4] = [
+        ReleaseType::Album,
+        ReleaseType::Single,
+        ReleaseType::Compilation,
+        ReleaseType::AppearsOn,
+    ];
+
+    /// Name used by the Spotify API and the command line, e.g. `"appears_on"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseType::Album => "album",
+            ReleaseType::Single => "single",
+            ReleaseType::Compilation => "compilation",
+            ReleaseType::AppearsOn => "appears_on",
+        }
+    }
+}
+
+impl fmt::Display for ReleaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReleaseType {
+    type Err = Error;
+
+    /// Accepts `"appears-on"` as well as `"appears_on"`.
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().replace('-', "_");
+        ReleaseType::ALL
+            .into_iter()
+            .find(|release_type| release_type.as_str().eq_ignore_ascii_case(&name))
+            .ok_or_else(|| Error::InvalidOptions(format!("Unknown release type: {s}")))
+    }
+}
+
+/// Options used to control how downloads are performed.
+///
+/// These map to CLI flags in the binary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadOptions {
+    /// Spotify URL (track/album/playlist/artist)
+    pub url: String,
+    /// More Spotify URLs downloaded in the same run as `url`, sharing one
+    /// session, one worker pool and one report
+    pub urls: Vec<String>,
+    /// Spotify Client ID
+    pub client_id: String,
+    /// Spotify Client Secret
+    pub client_secret: String,
+    /// Destination folder for audio files
+    pub output_dir: String,
+    /// Maximum number of concurrent downloads
+    pub concurrent_downloads: usize,
+    /// Skip duplicate tracks across collections
+    pub no_dupes: bool,
+    /// Target audio bitrate for ffmpeg
+    pub bitrate: Bitrate,
+    /// Output format/extension
+    pub format: AudioFormat,
+    /// Log verbosity level:
+    /// - `Full`: All debug information with progress bars
+    /// - `Info`: Standard information with progress bars
+    /// - `Debug`: Debug level logging with progress bars
+    /// - `NoBars`: Clean stdout output without progress bars (ideal for scripts/CI)
+    /// - `None`: Minimal output with progress bars
+    ///
+    /// The library only uses this to pick progress bars or log output; the
+    /// `rustifydl` binary also maps it to a logger configuration.
+    pub verbosity: Verbosity,
+    /// Don't write audio tags or cover art
+    pub no_tag: bool,
+    /// Release types downloaded for an artist URL
+    pub release_types: Vec<ReleaseType>,
+    /// ISO 3166-1 alpha-2 country code (e.g. `"DE"`) passed to every Spotify
+    /// lookup. Tracks are then relinked to versions playable there, and
+    /// tracks that aren't are reported as unavailable instead of downloaded.
+    /// `None` uses Spotify's default.
+    pub market: Option<String>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            url: String::new(),
+            urls: Vec::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            output_dir: "./output".to_string(),
+            concurrent_downloads: 15,
+            no_dupes: false,
+            bitrate: Bitrate::default(),
+            format: AudioFormat::default(),
+            verbosity: Verbosity::default(),
+            no_tag: false,
+            release_types: vec![
+                ReleaseType::Album,
+                ReleaseType::Single,
+                ReleaseType::Compilation,
+            ],
+            market: None,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Start building options for `url`, with every other field at its default.
+    pub fn builder(url: impl Into<String>) -> DownloadOptionsBuilder {
+        DownloadOptionsBuilder {
+            options: DownloadOptions {
+                url: url.into(),
+                ..DownloadOptions::default()
+            },
+        }
+    }
+
+    /// Every input of the run, `url` first, skipping blank entries.
+    pub fn inputs(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.urls.iter().map(String::as_str))
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+    }
+
+    /// Reject option sets that can't work, before anything touches the network.
+    ///
+    /// Returns [`Error::InvalidOptions`] naming the offending field. Spotify
+    /// credentials are checked by [`SpotifySource`](crate::SpotifySource), since
+    /// other metadata sources don't need them.
+    pub fn validate(&self) -> Result<()> {
+        if self.inputs().next().is_none() {
+            return Err(Error::InvalidOptions("No Spotify URL given".to_string()));
+        }
+        if self.output_dir.trim().is_empty() {
+            return Err(Error::InvalidOptions(
+                "Output directory must not be empty".to_string(),
+            ));
+        }
+        if self.concurrent_downloads == 0 {
+            return Err(Error::InvalidOptions(
+                "concurrent_downloads must be at least 1".to_string(),
+            ));
+        }
+        if self.release_types.is_empty() {
+            return Err(Error::InvalidOptions(
+                "release_types must name at least one release type".to_string(),
+            ));
+        }
+        if let Some(market) = &self.market
+            && !(market.len() == 2 && market.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return Err(Error::InvalidOptions(format!(
+                "market must be a two-letter country code like \"US\", got \"{market}\""
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builder returned by [`DownloadOptions::builder`].
+#[derive(Debug, Clone)]
+pub struct DownloadOptionsBuilder {
+    options: DownloadOptions,
+}
+
+impl DownloadOptionsBuilder {
+    /// More URLs to download in the same run.
+    pub fn urls<I, S>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.urls.extend(urls.into_iter().map(Into::into));
+        self
+    }
+
+    /// Spotify API client ID and secret.
+    pub fn client_credentials(
+        mut self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        self.options.client_id = client_id.into();
+        self.options.client_secret = client_secret.into();
+        self
+    }
+
+    /// Destination folder for audio files. Defaults to `./output`.
+    pub fn output_dir(mut self, output_dir: impl Into<String>) -> Self {
+        self.options.output_dir = output_dir.into();
+        self
+    }
+
+    /// Maximum number of concurrent downloads. Defaults to 15.
+    pub fn concurrent_downloads(mut self, concurrent_downloads: usize) -> Self {
+        self.options.concurrent_downloads = concurrent_downloads;
+        self
+    }
+
+    /// Skip duplicate tracks across collections.
+    pub fn no_dupes(mut self, no_dupes: bool) -> Self {
+        self.options.no_dupes = no_dupes;
+        self
+    }
+
+    /// Target audio bitrate. Defaults to 192k.
+    pub fn bitrate(mut self, bitrate: Bitrate) -> Self {
+        self.options.bitrate = bitrate;
+        self
+    }
+
+    /// Output format. Defaults to mp3.
+    pub fn format(mut self, format: AudioFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// Progress and log verbosity. Defaults to [`Verbosity::Info`].
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.options.verbosity = verbosity;
+        self
+    }
+
+    /// Don't write audio tags or cover art.
+    pub fn no_tag(mut self, no_tag: bool) -> Self {
+        self.options.no_tag = no_tag;
+        self
+    }
+
+    /// Release types downloaded for an artist URL. Defaults to albums,
+    /// singles and compilations.
+    pub fn release_types(mut self, release_types: impl IntoIterator<Item = ReleaseType>) -> Self {
+        self.options.release_types = release_types.into_iter().collect();
+        self
+    }
+
+    /// Country to look tracks up in, as an ISO 3166-1 alpha-2 code such as
+    /// `"de"` or `"US"`; letters are upper-cased.
+    pub fn market(mut self, market: impl Into<String>) -> Self {
+        self.options.market = Some(market.into().trim().to_uppercase());
+        self
+    }
+
+    /// Validate and return the options.
+    pub fn build(self) -> Result<DownloadOptions> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(options: &DownloadOptions) -> String {
+        match options.validate() {
+            Err(Error::InvalidOptions(message)) => message,
+            other => panic!("expected InvalidOptions, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_unusable_options() {
+        let options = DownloadOptions::builder("spotify:album:1DFixLWuPkv3KT3TnV35m3")
+            .build()
+            .unwrap();
+        assert!(options.validate().is_ok());
+
+        assert!(invalid(&DownloadOptions::default()).contains("URL"));
+        let blank = DownloadOptions {
+            url: "  ".to_string(),
+            urls: vec![String::new(), " ".to_string()],
+            ..options.clone()
+        };
+        assert!(invalid(&blank).contains("URL"));
+        let only_urls = DownloadOptions {
+            url: String::new(),
+            urls: vec!["liked".to_string()],
+            ..options.clone()
+        };
+        assert!(only_urls.validate().is_ok());
+
+        let no_workers = DownloadOptions {
+            concurrent_downloads: 0,
+            ..options.clone()
+        };
+        assert!(invalid(&no_workers).contains("concurrent_downloads"));
+        let no_output = DownloadOptions {
+            output_dir: " ".to_string(),
+            ..options.clone()
+        };
+        assert!(invalid(&no_output).contains("Output directory"));
+        let no_release_types = DownloadOptions {
+            release_types: Vec::new(),
+            ..options.clone()
+        };
+        assert!(invalid(&no_release_types).contains("release_types"));
+    }
+
+    #[test]
+    fn markets_are_two_letter_codes() {
+        let with_market = |market: &str| DownloadOptions {
+            url: "liked".to_string(),
+            market: Some(market.to_string()),
+            ..DownloadOptions::default()
+        };
+        assert!(with_market("DE").validate().is_ok());
+        for market in ["de", "DEU", "D", "", "D1"] {
+            assert!(invalid(&with_market(market)).contains("market"), "{market}");
+        }
+        // The builder upper-cases.
+        let options = DownloadOptions::builder("liked")
+            .market(" de ")
+            .build()
+            .unwrap();
+        assert_eq!(options.market.as_deref(), Some("DE"));
+    }
+
+    #[test]
+    fn option_values_parse_from_cli_strings() {
+        assert_eq!("192".parse::<Bitrate>().unwrap(), Bitrate::K192);
+        assert_eq!("192k".parse::<Bitrate>().unwrap(), Bitrate::K192);
+        assert_eq!(" 320K ".parse::<Bitrate>().unwrap(), Bitrate::K320);
+        assert!(matches!(
+            "191".parse::<Bitrate>(),
+            Err(Error::InvalidOptions(_))
+        ));
+
+        assert_eq!(
+            "appears-on".parse::<ReleaseType>().unwrap(),
+            ReleaseType::AppearsOn
+        );
+        assert_eq!(
+            "appears_on".parse::<ReleaseType>().unwrap(),
+            ReleaseType::AppearsOn
+        );
+        assert!("appears".parse::<ReleaseType>().is_err());
+
+        assert_eq!("FLAC".parse::<AudioFormat>().unwrap(), AudioFormat::Flac);
+        assert!("aac".parse::<AudioFormat>().is_err());
+        assert_eq!("no-bars".parse::<Verbosity>().unwrap(), Verbosity::NoBars);
+        assert!("loud".parse::<Verbosity>().is_err());
+
+        for format in AudioFormat::ALL {
+            assert_eq!(format.to_string().parse::<AudioFormat>().unwrap(), format);
+        }
+        for bitrate in Bitrate::ALL {
+            assert_eq!(bitrate.to_string().parse::<Bitrate>().unwrap(), bitrate);
+        }
+        for verbosity in Verbosity::ALL {
+            assert_eq!(verbosity.as_str().parse::<Verbosity>().unwrap(), verbosity);
+        }
+        for release_type in ReleaseType::ALL {
+            assert_eq!(
+                release_type.as_str().parse::<ReleaseType>().unwrap(),
+                release_type
+            );
+        }
+    }
+
+    #[test]
+    fn options_round_trip_through_serde() {
+        let options = DownloadOptions::builder("liked")
+            .urls(["spotify:album:1DFixLWuPkv3KT3TnV35m3"])
+            .concurrent_downloads(4)
+            .bitrate(Bitrate::K256)
+            .format(AudioFormat::Opus)
+            .verbosity(Verbosity::NoBars)
+            .release_types([ReleaseType::Single, ReleaseType::AppearsOn])
+            .market("de")
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(json["bitrate"], "256k");
+        assert_eq!(json["format"], "opus");
+        assert_eq!(json["verbosity"], "no-bars");
+        assert_eq!(
+            json["release_types"],
+            serde_json::json!(["single", "appears_on"])
+        );
+
+        let back: DownloadOptions = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+
+        // Missing fields take their defaults.
+        let partial: DownloadOptions =
+            serde_json::from_str(r#"{"url": "liked", "bitrate": "320k"}"#).unwrap();
+        assert_eq!(partial.bitrate, Bitrate::K320);
+        assert_eq!(partial.concurrent_downloads, 15);
+        assert_eq!(partial.output_dir, "./output");
+        assert!(partial.validate().is_ok());
+    }
+}
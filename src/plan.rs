@@ -0,0 +1,372 @@
+//! The ordered list of tracks a run will download.
+//!
+//! Fetchers turn catalog results into a `Vec<PlannedTrack>` in source order.
+//! Each entry keeps its position in the playlist or album and gets an output
+//! name that no other entry in the plan uses, so two different tracks never
+//! write to the same file. Plans for several inputs are combined with
+//! [`merge_plans`].
+//!
+//! Example
+//! ```
+//! use rustifydl::TrackInfo;
+//! use rustifydl::plan::plan_tracks;
+//!
+//! let intro = |id: &str, album: &str| {
+//!     let mut track = TrackInfo {
+//!         id: id.into(),
+//!         name: "Intro".into(),
+//!         artists: vec!["The xx".into()],
+//!         ..TrackInfo::default()
+//!     };
+//!     track.album.name = album.into();
+//!     track
+//! };
+//! let plan = plan_tracks(
+//!     vec![intro("a", "xx"), intro("b", "xx"), intro("c", "Live"), intro("a", "xx")],
+//!     false,
+//! );
+//! let names: Vec<_> = plan.iter().map(|t| t.output_name.as_str()).collect();
+//! assert_eq!(names, ["The xx - Intro", "The xx - xx - Intro", "The xx - Live - Intro"]);
+//! assert_eq!(plan[2].position, 2);
+//! ```
+
+use std::collections::HashSet;
+
+use crate::{
+    sanitize_filename,
+    source::{AlbumInfo, PlaylistInfo, TrackInfo},
+};
+
+/// One track to download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedTrack {
+    /// Zero-based position in the source playlist or album. Entries dropped
+    /// from the plan leave gaps, so this is not always the index in the plan.
+    pub position: usize,
+    /// Subfolder of the output directory, if the plan spans several albums
+    /// or playlists, or the track is a podcast episode.
+    pub folder: Option<String>,
+    /// File name without extension, unique within its folder.
+    pub output_name: String,
+    /// The catalog track.
+    pub track: TrackInfo,
+    /// Whether the track is part of a whole album being downloaded, so its
+    /// audio is taken from one matching release where possible.
+    pub whole_album: bool,
+}
+
+impl PlannedTrack {
+    /// Catalog ID the entry is keyed by.
+    pub fn id(&self) -> &str {
+        &self.track.id
+    }
+
+    /// Path of the output file relative to the output directory, without
+    /// extension, e.g. `"Album/Artists - Title"`.
+    pub fn relative_name(&self) -> String {
+        match &self.folder {
+            Some(folder) => format!("{folder}/{}", self.output_name),
+            None => self.output_name.clone(),
+        }
+    }
+}
+
+/// Plan `tracks`, keeping their order.
+///
+/// A track ID that appears more than once is planned once. With `no_dupes`,
+/// downloadable tracks whose `"Artists - Title"` matches an earlier
+/// downloadable entry are dropped as well; other clashes are named
+/// `"Artists - Album - Title"`, with a `" (2)"`-style suffix if that is taken
+/// too. Names are compared case-insensitively, since not every filesystem
+/// tells them apart.
+///
+/// Podcast episodes go into a folder named after their show. Entries
+/// without an ID, such as removed playlist entries, are never merged, and
+/// entries that can't be downloaded are always kept for the report.
+pub fn plan_tracks(tracks: Vec<TrackInfo>, no_dupes: bool) -> Vec<PlannedTrack> {
+    let mut ids = HashSet::new();
+    let mut names = HashSet::new();
+    let mut downloadable_names = HashSet::new();
+    let mut plan = Vec::with_capacity(tracks.len());
+
+    for (position, track) in tracks.into_iter().enumerate() {
+        if !track.id.is_empty() && !ids.insert(track.id.clone()) {
+            continue;
+        }
+        let display_name = sanitize_filename(&track.display_name());
+        let key = display_name.to_lowercase();
+        if track.status.is_downloadable() && !downloadable_names.insert(key.clone()) && no_dupes {
+            continue;
+        }
+        let output_name = if !names.contains(&key) {
+            display_name
+        } else {
+            let with_album = sanitize_filename(&format!(
+                "{} - {} - {}",
+                track.artist_names(),
+                track.album.name,
+                track.name
+            ));
+            let mut candidate = with_album.clone();
+            let mut n = 2;
+            while names.contains(&candidate.to_lowercase()) {
+                candidate = format!("{with_album} ({n})");
+                n += 1;
+            }
+            candidate
+        };
+        names.insert(output_name.to_lowercase());
+        plan.push(PlannedTrack {
+            position,
+            folder: track
+                .is_episode()
+                .then(|| sanitize_filename(&track.album.name)),
+            output_name,
+            track,
+            whole_album: false,
+        });
+    }
+    plan
+}
+
+/// Plan several albums, one folder per album, keeping album and track order.
+///
+/// A recording that already appeared on an earlier album (same ISRC, or the
+/// same track ID when there is none) is dropped, so re-releases and deluxe
+/// editions only add their new tracks. Albums left without tracks get no
+/// folder. Folder names are made unique the same way file names are.
+pub fn plan_albums(albums: Vec<(AlbumInfo, Vec<TrackInfo>)>) -> Vec<PlannedTrack> {
+    let mut recordings = HashSet::new();
+    let mut folders = HashSet::new();
+    let mut plan = Vec::new();
+
+    for (album, tracks) in albums {
+        let (positions, tracks): (Vec<usize>, Vec<TrackInfo>) = tracks
+            .into_iter()
+            .enumerate()
+            .filter(|(_, track)| recordings.insert(track.isrc.clone().unwrap_or(track.id.clone())))
+            .unzip();
+        if tracks.is_empty() {
+            continue;
+        }
+
+        let folder = unique_folder(&album.name, album.year(), &mut folders);
+        plan.extend(
+            plan_tracks(tracks, false)
+                .into_iter()
+                .map(|planned| PlannedTrack {
+                    position: positions[planned.position],
+                    folder: Some(folder.clone()),
+                    ..planned
+                }),
+        );
+    }
+    plan
+}
+
+/// Plan several playlists, one folder per playlist, keeping playlist and
+/// track order.
+///
+/// Within a playlist, tracks are planned as by [`plan_tracks`], so episodes
+/// go into a show folder inside the playlist folder. A track already in an
+/// earlier playlist is dropped, so it is downloaded only once. Playlists left
+/// without tracks get no folder.
+pub fn plan_playlists(
+    playlists: Vec<(PlaylistInfo, Vec<TrackInfo>)>,
+    no_dupes: bool,
+) -> Vec<PlannedTrack> {
+    let mut ids = HashSet::new();
+    let mut folders = HashSet::new();
+    let mut plan = Vec::new();
+
+    for (playlist, tracks) in playlists {
+        let planned: Vec<PlannedTrack> = plan_tracks(tracks, no_dupes)
+            .into_iter()
+            .filter(|planned| planned.id().is_empty() || ids.insert(planned.id().to_string()))
+            .collect();
+        if planned.is_empty() {
+            continue;
+        }
+
+        let folder = unique_folder(&playlist.name, None, &mut folders);
+        plan.extend(planned.into_iter().map(|planned| PlannedTrack {
+            folder: Some(match &planned.folder {
+                Some(show) => format!("{folder}/{show}"),
+                None => folder.clone(),
+            }),
+            ..planned
+        }));
+    }
+    plan
+}
+
+/// A sanitized folder name for `name` not yet in `folders` (lowercased),
+/// trying `" (year)"` and then `" (2)"`-style suffixes. The result is added
+/// to `folders`.
+fn unique_folder(name: &str, year: Option<u32>, folders: &mut HashSet<String>) -> String {
+    let base = sanitize_filename(name);
+    let mut folder = base.clone();
+    if folders.contains(&folder.to_lowercase())
+        && let Some(year) = year
+    {
+        folder = format!("{base} ({year})");
+    }
+    let mut n = 2;
+    while folders.contains(&folder.to_lowercase()) {
+        folder = format!("{base} ({n})");
+        n += 1;
+    }
+    folders.insert(folder.to_lowercase());
+    folder
+}
+
+/// Combine the plans of several inputs into one, keeping input order.
+///
+/// A track ID already planned by an earlier input is dropped. With
+/// `no_dupes`, so is a downloadable track whose `"Artists - Title"` an
+/// earlier input already has for a downloadable track. Output paths that
+/// clash with an earlier input get a `" (2)"`-style suffix.
+pub fn merge_plans(plans: Vec<Vec<PlannedTrack>>, no_dupes: bool) -> Vec<PlannedTrack> {
+    let mut ids = HashSet::new();
+    let mut paths = HashSet::new();
+    let mut earlier_names = HashSet::new();
+    let mut merged = Vec::new();
+
+    for plan in plans {
+        let mut names = HashSet::new();
+        for mut planned in plan {
+            let display_name = planned.track.display_name().to_lowercase();
+            let downloadable = planned.track.status.is_downloadable();
+            if (!planned.id().is_empty() && !ids.insert(planned.id().to_string()))
+                || (no_dupes && downloadable && earlier_names.contains(&display_name))
+            {
+                continue;
+            }
+            if downloadable {
+                names.insert(display_name);
+            }
+
+            let base = planned.output_name.clone();
+            let mut n = 2;
+            while paths.contains(&planned.relative_name().to_lowercase()) {
+                planned.output_name = format!("{base} ({n})");
+                n += 1;
+            }
+            paths.insert(planned.relative_name().to_lowercase());
+            merged.push(planned);
+        }
+        earlier_names.extend(names);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::EntryStatus;
+
+    fn track(id: &str, name: &str, album: &str) -> TrackInfo {
+        let mut track = TrackInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            artists: vec!["Artist".to_string()],
+            ..TrackInfo::default()
+        };
+        track.album.name = album.to_string();
+        track
+    }
+
+    fn names(plan: &[PlannedTrack]) -> Vec<String> {
+        plan.iter().map(PlannedTrack::relative_name).collect()
+    }
+
+    #[test]
+    fn no_dupes_keeps_entries_that_cant_be_downloaded() {
+        let local = TrackInfo {
+            status: EntryStatus::LocalFile,
+            ..track("spotify:local:a", "Song", "")
+        };
+        let unavailable = TrackInfo {
+            status: EntryStatus::Unavailable("unavailable in market".to_string()),
+            ..track("b", "Song", "Single")
+        };
+        let plan = plan_tracks(
+            vec![
+                local,
+                track("a", "Song", "Album"),
+                unavailable,
+                track("c", "Song", "Live"),
+            ],
+            true,
+        );
+        assert_eq!(
+            names(&plan),
+            [
+                "Artist - Song",
+                "Artist - Album - Song",
+                "Artist - Single - Song"
+            ]
+        );
+        assert_eq!(plan[1].track.status, EntryStatus::Downloadable);
+        assert!(matches!(plan[2].track.status, EntryStatus::Unavailable(_)));
+    }
+
+    #[test]
+    fn albums_skip_recordings_already_planned() {
+        let album = |id: &str, date: &str| AlbumInfo {
+            id: id.to_string(),
+            name: "Album".to_string(),
+            release_date: date.to_string(),
+            ..AlbumInfo::default()
+        };
+        let recording = |id: &str, name: &str, isrc: Option<&str>| TrackInfo {
+            isrc: isrc.map(str::to_string),
+            ..track(id, name, "Album")
+        };
+        let plan = plan_albums(vec![
+            (
+                album("original", "2020"),
+                vec![
+                    recording("a1", "One", Some("ISRC1")),
+                    recording("a2", "Two", None),
+                ],
+            ),
+            (
+                album("deluxe", "2021-05-01"),
+                vec![
+                    recording("b1", "One", Some("ISRC1")),
+                    recording("a2", "Two", None),
+                    recording("b3", "Three", Some("ISRC3")),
+                ],
+            ),
+        ]);
+        assert_eq!(
+            names(&plan),
+            [
+                "Album/Artist - One",
+                "Album/Artist - Two",
+                "Album (2021)/Artist - Three"
+            ]
+        );
+        assert_eq!(plan[2].position, 2);
+    }
+
+    #[test]
+    fn merged_plans_get_unique_paths() {
+        let first = plan_tracks(vec![track("a", "Song", "Album")], false);
+        let second = plan_tracks(
+            vec![track("b", "SONG", "Single"), track("c", "Other", "Single")],
+            false,
+        );
+        let again = plan_tracks(vec![track("a", "Song", "Album")], false);
+        let merged = merge_plans(vec![first.clone(), second.clone(), again], false);
+        assert_eq!(
+            names(&merged),
+            ["Artist - Song", "Artist - SONG (2)", "Artist - Other"]
+        );
+        assert_eq!(merged[1].id(), "b");
+
+        let merged = merge_plans(vec![first, second], true);
+        assert_eq!(names(&merged), ["Artist - Song", "Artist - Other"]);
+    }
+}
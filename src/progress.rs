@@ -0,0 +1,254 @@
+//! Progress events emitted while a download runs.
+//!
+//! The pipeline never draws anything itself: it sends [`ProgressEvent`]s to a
+//! [`ProgressObserver`]. Two observers ship with the crate:
+//! - [`IndicatifObserver`]: one spinner per track on an `indicatif::MultiProgress`.
+//! - [`LogObserver`]: plain `log` records, for scripts and CI.
+//!
+//! GUIs and servers can implement the trait themselves, or pass an
+//! `UnboundedSender<ProgressEvent>` and consume the events from a channel.
+//!
+//! Example
+//! ```no_run
+//! use std::sync::Arc;
+//! use rustifydl::{download_spotify_with_observer, DownloadOptions, ProgressEvent};
+//!
+//! # async fn run(opts: DownloadOptions) -> Result<(), rustifydl::Error> {
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<ProgressEvent>();
+//! tokio::spawn(async move {
+//!     while let Some(event) = rx.recv().await {
+//!         println!("{event:?}");
+//!     }
+//! });
+//! download_spotify_with_observer(opts, String::new(), Arc::new(tx)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use log::{debug, error, info};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Identifies the track an event belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackProgress {
+    /// Zero-based position of the track in the run.
+    pub index: usize,
+    /// Number of tracks in the run.
+    pub total: usize,
+    /// Display name, e.g. `"Artists - Title"`.
+    pub name: String,
+}
+
+impl TrackProgress {
+    /// `"3/12"` style position used in messages.
+    pub fn position(&self) -> String {
+        format!("{}/{}", self.index + 1, self.total)
+    }
+}
+
+/// Something that happened during a download run.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// The Spotify input resolved to `total` tracks.
+    Resolved { total: usize },
+    /// Searching YouTube Music for the track.
+    Searching { track: TrackProgress },
+    /// A YouTube video was picked for the track.
+    Matched {
+        track: TrackProgress,
+        youtube_id: String,
+    },
+    /// yt-dlp reported download progress. `total` is `None` when unknown.
+    Downloading {
+        track: TrackProgress,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// ffmpeg is converting the download to the output format.
+    Transcoding { track: TrackProgress },
+    /// Tags and artwork are being written.
+    Tagging { track: TrackProgress },
+    /// The track is done. `skipped` is `true` when the file already existed.
+    Finished { track: TrackProgress, skipped: bool },
+    /// The entry can't be downloaded (local file, removed or unavailable);
+    /// `reason` says which.
+    NotDownloadable {
+        track: TrackProgress,
+        reason: String,
+    },
+    /// The track failed; `error` is the rendered [`Error`](crate::Error).
+    Failed { track: TrackProgress, error: String },
+    /// Every track has been processed.
+    Done { elapsed: Duration },
+}
+
+/// Receives [`ProgressEvent`]s from the pipeline.
+///
+/// Called from several tasks at once, so implementations must be cheap and
+/// must not block.
+pub trait ProgressObserver: Send + Sync {
+    /// Handle one event.
+    fn on_event(&self, event: &ProgressEvent);
+}
+
+/// Ignores every event.
+pub struct NoopObserver;
+
+impl ProgressObserver for NoopObserver {
+    fn on_event(&self, _event: &ProgressEvent) {}
+}
+
+/// Forwards events to a channel. Events are dropped once the receiver is gone.
+impl ProgressObserver for UnboundedSender<ProgressEvent> {
+    fn on_event(&self, event: &ProgressEvent) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Reports progress as `log` records, one line per stage.
+pub struct LogObserver;
+
+impl ProgressObserver for LogObserver {
+    fn on_event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Resolved { total } => info!("Found {total} tracks"),
+            ProgressEvent::Searching { track } => {
+                info!("{} Starting download: {}", track.position(), track.name)
+            }
+            ProgressEvent::Matched { track, youtube_id } => {
+                debug!(
+                    "{} Matched {} to {youtube_id}",
+                    track.position(),
+                    track.name
+                )
+            }
+            ProgressEvent::Downloading {
+                track, downloaded, ..
+            } => debug!(
+                "{} Downloading {}: {}",
+                track.position(),
+                track.name,
+                HumanBytes(*downloaded)
+            ),
+            ProgressEvent::Transcoding { track } => {
+                debug!("{} Transcoding: {}", track.position(), track.name)
+            }
+            ProgressEvent::Tagging { track } => {
+                info!("{} Tagging: {}", track.position(), track.name)
+            }
+            ProgressEvent::Finished {
+                track,
+                skipped: true,
+            } => info!("File already exists, skipping: {}", track.name),
+            ProgressEvent::Finished { track, .. } => info!("Finished {}!", track.name),
+            ProgressEvent::NotDownloadable { track, reason } => {
+                info!("Not downloading {}: {reason}", track.name)
+            }
+            ProgressEvent::Failed { track, error } => {
+                error!("Task failed: {}: {error}", track.name)
+            }
+            ProgressEvent::Done { elapsed } => info!("Took {}s", elapsed.as_secs()),
+        }
+    }
+}
+
+/// Draws one spinner per track on a shared [`MultiProgress`].
+pub struct IndicatifObserver {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<usize, ProgressBar>>,
+}
+
+impl IndicatifObserver {
+    /// Draw on `multi`. Pass the same `MultiProgress` to
+    /// `indicatif_log_bridge::LogWrapper` so log lines don't tear the bars.
+    pub fn new(multi: MultiProgress) -> Self {
+        IndicatifObserver {
+            multi,
+            bars: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn bar(&self, track: &TrackProgress) -> ProgressBar {
+        let mut bars = self.bars.lock().unwrap();
+        bars.entry(track.index)
+            .or_insert_with(|| {
+                let bar = self.multi.add(ProgressBar::new_spinner());
+                bar.set_style(ProgressStyle::with_template("{spinner:.cyan} {msg}").unwrap());
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            })
+            .clone()
+    }
+
+    fn finish(&self, track: &TrackProgress, message: String) {
+        self.bar(track).finish_with_message(message);
+        self.bars.lock().unwrap().remove(&track.index);
+    }
+}
+
+impl ProgressObserver for IndicatifObserver {
+    fn on_event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Resolved { .. } => {}
+            ProgressEvent::Searching { track } => self.bar(track).set_message(format!(
+                "{} Searching: {}",
+                track.position(),
+                track.name
+            )),
+            ProgressEvent::Matched { track, .. } => self.bar(track).set_message(format!(
+                "{} Downloading: {}",
+                track.position(),
+                track.name
+            )),
+            ProgressEvent::Downloading {
+                track,
+                downloaded,
+                total,
+            } => {
+                let size = match total {
+                    Some(total) => format!("{}/{}", HumanBytes(*downloaded), HumanBytes(*total)),
+                    None => HumanBytes(*downloaded).to_string(),
+                };
+                self.bar(track).set_message(format!(
+                    "{} Downloading: {} ({size})",
+                    track.position(),
+                    track.name
+                ))
+            }
+            ProgressEvent::Transcoding { track } => self.bar(track).set_message(format!(
+                "{} Transcoding: {}",
+                track.position(),
+                track.name
+            )),
+            ProgressEvent::Tagging { track } => {
+                self.bar(track)
+                    .set_message(format!("{} Tagging: {}", track.position(), track.name))
+            }
+            ProgressEvent::Finished {
+                track,
+                skipped: true,
+            } => self.finish(
+                track,
+                format!("File already exists, skipping!: {}", track.name),
+            ),
+            ProgressEvent::Finished { track, .. } => {
+                self.finish(track, format!("Finished {}!", track.name))
+            }
+            ProgressEvent::NotDownloadable { track, reason } => {
+                self.finish(track, format!("Not downloading {}: {reason}", track.name))
+            }
+            ProgressEvent::Failed { track, error } => {
+                self.finish(track, format!("Failed {}: {error}", track.name))
+            }
+            ProgressEvent::Done { elapsed } => {
+                let bar = self.multi.add(ProgressBar::new(100));
+                bar.set_style(ProgressStyle::with_template("{msg}").unwrap());
+                bar.finish_with_message(format!("Took {}s", elapsed.as_secs()));
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Per-track results of a download run.
+//!
+//! [`download_spotify`](crate::download_spotify) returns a [`DownloadReport`]
+//! with one [`TrackReport`] per resolved track, so callers can see exactly
+//! which tracks completed, were skipped or failed, and why. Playlist entries
+//! that can't be downloaded (local files, removed or unavailable tracks) are
+//! listed too, and [`DownloadReport::write_not_downloaded`] saves them for
+//! scripts.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{Error, Result, audio::MatchMethod, source::EntryStatus};
+
+/// File in the output directory listing the entries a run couldn't download.
+pub const NOT_DOWNLOADED_FILE: &str = "not_downloaded.json";
+
+/// What happened to a single track.
+#[derive(Debug)]
+pub enum TrackOutcome {
+    /// Not processed yet.
+    Pending,
+    /// Downloaded, transcoded and (unless disabled) tagged.
+    Completed,
+    /// The output file already existed.
+    Skipped,
+    /// The entry can't be downloaded: a local file, a removed track or one
+    /// unavailable in the market. Holds the entry's status.
+    NotDownloadable(EntryStatus),
+    /// A stage failed; the error says which one.
+    Failed(Error),
+}
+
+/// Time spent in each stage of a track. `None` when the stage didn't run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimings {
+    /// YouTube Music search.
+    pub search: Option<Duration>,
+    /// yt-dlp download plus ffmpeg transcode.
+    pub download: Option<Duration>,
+    /// Writing tags and artwork.
+    pub tagging: Option<Duration>,
+}
+
+impl StageTimings {
+    /// Sum of all stages that ran.
+    pub fn total(&self) -> Duration {
+        [self.search, self.download, self.tagging]
+            .into_iter()
+            .flatten()
+            .sum()
+    }
+}
+
+/// Result for one track of the run.
+#[derive(Debug)]
+pub struct TrackReport {
+    /// Display name used for the output file, e.g. `"Artists - Title"`.
+    pub name: String,
+    /// Spotify track ID, as requested: relinked tracks keep their original ID.
+    pub spotify_id: String,
+    /// YouTube video ID picked for the download, if the search got that far.
+    pub youtube_id: Option<String>,
+    /// How that video was found.
+    pub match_method: Option<MatchMethod>,
+    /// Final path of the audio file.
+    pub output_path: PathBuf,
+    /// Pending until the track is processed, then completed, skipped, not
+    /// downloadable or failed.
+    pub outcome: TrackOutcome,
+    /// Time spent per stage.
+    pub timings: StageTimings,
+}
+
+impl TrackReport {
+    /// Start a report for a track that hasn't been processed yet.
+    pub fn new(name: &str, spotify_id: &str, output_path: PathBuf) -> Self {
+        TrackReport {
+            name: name.to_string(),
+            spotify_id: spotify_id.to_string(),
+            youtube_id: None,
+            match_method: None,
+            output_path,
+            outcome: TrackOutcome::Pending,
+            timings: StageTimings::default(),
+        }
+    }
+}
+
+/// Summary of a whole [`download_spotify`](crate::download_spotify) run.
+#[derive(Debug, Default)]
+pub struct DownloadReport {
+    /// One entry per track.
+    pub tracks: Vec<TrackReport>,
+    /// Wall-clock time of the run.
+    pub elapsed: Duration,
+}
+
+impl DownloadReport {
+    /// Number of tracks that were downloaded.
+    pub fn completed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TrackOutcome::Completed))
+    }
+
+    /// Number of tracks skipped because the file already existed.
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TrackOutcome::Skipped))
+    }
+
+    /// Number of entries that can't be downloaded.
+    pub fn not_downloadable(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TrackOutcome::NotDownloadable(_)))
+    }
+
+    /// Number of tracks that failed.
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TrackOutcome::Failed(_)))
+    }
+
+    /// `true` when at least one track failed.
+    pub fn has_failures(&self) -> bool {
+        self.failed() > 0
+    }
+
+    /// Write the entries that couldn't be downloaded to
+    /// [`NOT_DOWNLOADED_FILE`] in `dir`, as a JSON array of
+    /// `{"name", "spotify_id", "status", "reason"}` objects, and return its
+    /// path. When every entry could be downloaded, a file left by an earlier
+    /// run is removed instead and `None` is returned.
+    pub fn write_not_downloaded(&self, dir: &Path) -> Result<Option<PathBuf>> {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            name: &'a str,
+            spotify_id: &'a str,
+            status: &'static str,
+            reason: String,
+        }
+
+        let entries: Vec<Entry> = self
+            .tracks
+            .iter()
+            .filter_map(|track| match &track.outcome {
+                TrackOutcome::NotDownloadable(status) => Some(Entry {
+                    name: &track.name,
+                    spotify_id: &track.spotify_id,
+                    status: status.as_str(),
+                    reason: status.to_string(),
+                }),
+                _ => None,
+            })
+            .collect();
+        let path = dir.join(NOT_DOWNLOADED_FILE);
+        if entries.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(None);
+        }
+        fs::create_dir_all(dir)?;
+        let content = serde_json::to_string_pretty(&entries).map_err(std::io::Error::other)?;
+        fs::write(&path, content)?;
+        Ok(Some(path))
+    }
+
+    fn count(&self, predicate: impl Fn(&TrackOutcome) -> bool) -> usize {
+        self.tracks
+            .iter()
+            .filter(|track| predicate(&track.outcome))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(outcomes: Vec<(&str, TrackOutcome)>) -> DownloadReport {
+        let tracks = outcomes
+            .into_iter()
+            .map(|(id, outcome)| TrackReport {
+                outcome,
+                ..TrackReport::new(&format!("Artist - {id}"), id, PathBuf::from(id))
+            })
+            .collect();
+        DownloadReport {
+            tracks,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn not_downloaded_lists_only_entries_that_cant_be_downloaded() {
+        let dir = std::env::temp_dir().join(format!("rustifydl-report-{}", std::process::id()));
+        let report = report(vec![
+            ("done", TrackOutcome::Completed),
+            (
+                "local",
+                TrackOutcome::NotDownloadable(EntryStatus::LocalFile),
+            ),
+            (
+                "failed",
+                TrackOutcome::Failed(Error::NoResults("q".to_string())),
+            ),
+            (
+                "region",
+                TrackOutcome::NotDownloadable(EntryStatus::Unavailable(
+                    "unavailable in market".to_string(),
+                )),
+            ),
+        ]);
+
+        let path = report.write_not_downloaded(&dir).unwrap().unwrap();
+        assert_eq!(path, dir.join(NOT_DOWNLOADED_FILE));
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            serde_json::json!([
+                {
+                    "name": "Artist - local",
+                    "spotify_id": "local",
+                    "status": "local_file",
+                    "reason": "local file, not on Spotify",
+                },
+                {
+                    "name": "Artist - region",
+                    "spotify_id": "region",
+                    "status": "unavailable",
+                    "reason": "unavailable in market",
+                },
+            ])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn not_downloaded_file_of_an_earlier_run_is_removed() {
+        let dir = std::env::temp_dir().join(format!("rustifydl-stale-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(NOT_DOWNLOADED_FILE);
+        fs::write(&path, "[]").unwrap();
+
+        let report = report(vec![
+            ("done", TrackOutcome::Completed),
+            ("there", TrackOutcome::Skipped),
+        ]);
+        assert_eq!(report.write_not_downloaded(&dir).unwrap(), None);
+        assert!(!path.exists());
+        // Nothing to remove is fine too.
+        assert_eq!(report.write_not_downloaded(&dir).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
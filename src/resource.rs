@@ -0,0 +1,493 @@
+//! Parsing Spotify links and URIs into a typed [`SpotifyResource`].
+//!
+//! Accepted input:
+//! - `https://open.spotify.com/<kind>/<id>`, with or without an `intl-xx/`
+//!   prefix, query string or fragment
+//! - embed URLs: `https://open.spotify.com/embed/<kind>/<id>` and
+//!   `https://embed.spotify.com/?uri=spotify:<kind>:<id>`
+//! - `spotify:<kind>:<id>` URIs, including legacy `spotify:user:<name>:playlist:<id>`
+//!
+//! `spotify.link` share links have to be followed first, see [`resolve_short_link`].
+//! The logged-in user's library is addressed by keyword instead, see [`LibraryInput`].
+//!
+//! Example
+//! ```
+//! use rustifydl::resource::{ResourceKind, SpotifyResource};
+//!
+//! let playlist = SpotifyResource::parse(
+//!     "https://open.spotify.com/intl-de/playlist/37i9dQZF1DXcBWIGoYBM5M?si=abc",
+//! )
+//! .unwrap();
+//! assert_eq!(playlist.kind, ResourceKind::Playlist);
+//! assert_eq!(playlist.id, "37i9dQZF1DXcBWIGoYBM5M");
+//!
+//! let track = SpotifyResource::parse("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap();
+//! assert_eq!(track.url(), "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC");
+//!
+//! let album = SpotifyResource::parse("https://open.spotify.com/embed/album/1DFixLWuPkv3KT3TnV35m3").unwrap();
+//! assert_eq!(album.uri(), "spotify:album:1DFixLWuPkv3KT3TnV35m3");
+//!
+//! // IDs are 22 base62 characters.
+//! assert!(SpotifyResource::parse("https://open.spotify.com/track/not-an-id").is_err());
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+
+use crate::{Error, Result};
+
+/// What a Spotify ID refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Track,
+    Album,
+    Playlist,
+    Artist,
+    /// A podcast episode.
+    Episode,
+    /// A podcast show.
+    Show,
+}
+
+impl ResourceKind {
+    /// Every kind RustifyDL can download.
+    pub const ALL: [ResourceKind; 6] = [
+        ResourceKind::Track,
+        ResourceKind::Album,
+        ResourceKind::Playlist,
+        ResourceKind::Artist,
+        ResourceKind::Episode,
+        ResourceKind::Show,
+    ];
+
+    /// Name used in URLs and URIs, e.g. `"playlist"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourceKind::Track => "track",
+            ResourceKind::Album => "album",
+            ResourceKind::Playlist => "playlist",
+            ResourceKind::Artist => "artist",
+            ResourceKind::Episode => "episode",
+            ResourceKind::Show => "show",
+        }
+    }
+
+    fn from_segment(segment: &str) -> Option<Self> {
+        ResourceKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == segment)
+    }
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A part of the logged-in user's library, requested by keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LibraryInput {
+    /// `liked`: Liked Songs.
+    LikedSongs,
+    /// `saved-albums`: every saved album.
+    SavedAlbums,
+    /// `me/playlists`: every playlist the user owns or follows.
+    Playlists,
+}
+
+impl LibraryInput {
+    /// Every library input.
+    pub const ALL: [LibraryInput; 3] = [
+        LibraryInput::LikedSongs,
+        LibraryInput::SavedAlbums,
+        LibraryInput::Playlists,
+    ];
+
+    /// Keyword that selects this input, e.g. `"liked"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LibraryInput::LikedSongs => "liked",
+            LibraryInput::SavedAlbums => "saved-albums",
+            LibraryInput::Playlists => "me/playlists",
+        }
+    }
+
+    /// Recognize a library keyword, ignoring case. The web player's Liked
+    /// Songs link, `https://open.spotify.com/collection/tracks`, is accepted
+    /// too.
+    ///
+    /// ```
+    /// use rustifydl::resource::LibraryInput;
+    ///
+    /// assert_eq!(LibraryInput::parse("Liked"), Some(LibraryInput::LikedSongs));
+    /// assert_eq!(LibraryInput::parse("me/playlists"), Some(LibraryInput::Playlists));
+    /// assert_eq!(LibraryInput::parse("spotify:album:1DFixLWuPkv3KT3TnV35m3"), None);
+    /// ```
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        let input = input.trim_end_matches('/');
+        if input.ends_with("open.spotify.com/collection/tracks") {
+            return Some(LibraryInput::LikedSongs);
+        }
+        LibraryInput::ALL
+            .into_iter()
+            .find(|library| library.as_str() == input)
+    }
+}
+
+impl fmt::Display for LibraryInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A Spotify object identified by its kind and base62 ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpotifyResource {
+    /// What the ID refers to.
+    pub kind: ResourceKind,
+    /// The 22-character base62 ID.
+    pub id: String,
+}
+
+impl SpotifyResource {
+    /// Parse a Spotify URL, embed URL or URI.
+    ///
+    /// Returns [`Error::InvalidUrl`] if `input` isn't a Spotify link with a
+    /// valid ID, and [`Error::Unsupported`] for links to kinds RustifyDL
+    /// can't download (e.g. `user/`).
+    pub fn parse(input: &str) -> Result<Self> {
+        let trimmed = input.trim();
+        let (kind, id) = if let Some(uri) = trimmed.strip_prefix("spotify:") {
+            parse_uri(uri)
+        } else {
+            parse_url(trimmed)
+        }
+        .ok_or_else(|| Error::InvalidUrl(input.to_string()))?;
+
+        let kind = ResourceKind::from_segment(&kind).ok_or_else(|| {
+            Error::Unsupported(format!("Spotify {kind} links can't be downloaded: {input}"))
+        })?;
+        if !is_base62_id(&id) {
+            return Err(Error::InvalidUrl(input.to_string()));
+        }
+        Ok(SpotifyResource { kind, id })
+    }
+
+    /// `spotify:<kind>:<id>`.
+    pub fn uri(&self) -> String {
+        format!("spotify:{}:{}", self.kind, self.id)
+    }
+
+    /// `https://open.spotify.com/<kind>/<id>`.
+    pub fn url(&self) -> String {
+        format!("https://open.spotify.com/{}/{}", self.kind, self.id)
+    }
+}
+
+impl FromStr for SpotifyResource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        SpotifyResource::parse(s)
+    }
+}
+
+impl fmt::Display for SpotifyResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.uri())
+    }
+}
+
+/// `<kind>:<id>`, or the legacy `user:<name>:<kind>:<id>`.
+fn parse_uri(uri: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = uri.split(':').collect();
+    match parts.as_slice() {
+        ["user", _, kind, id] | [kind, id] => Some((kind.to_string(), id.to_string())),
+        _ => None,
+    }
+}
+
+fn parse_url(url: &str) -> Option<(String, String)> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let (path, query) = match rest.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (rest, ""),
+    };
+    let path = path.split('#').next().unwrap_or_default();
+
+    match host {
+        "open.spotify.com" | "play.spotify.com" => {}
+        // Legacy embed widget: the resource is a URI in the `uri` query parameter.
+        "embed.spotify.com" => {
+            let uri = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("uri="))?
+                .replace("%3A", ":")
+                .replace("%3a", ":");
+            return parse_uri(uri.strip_prefix("spotify:")?);
+        }
+        _ => return None,
+    }
+
+    let mut segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .skip_while(|segment| segment.starts_with("intl-") || *segment == "embed")
+        .peekable();
+    if segments.peek() == Some(&"user") {
+        segments.nth(1);
+    }
+    let kind = segments.next()?;
+    let id = segments.next()?;
+    Some((kind.to_string(), id.to_string()))
+}
+
+/// Spotify IDs are 22 characters of `[0-9A-Za-z]`.
+fn is_base62_id(id: &str) -> bool {
+    id.len() == 22 && id.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Hosts that serve Spotify share links redirecting to `open.spotify.com`.
+const SHORT_LINK_HOSTS: [&str; 2] = ["spotify.link", "spotify.app.link"];
+
+/// Whether `url` is a `spotify.link` share link that needs
+/// [`resolve_short_link`] before it can be classified.
+pub fn is_short_link(url: &str) -> bool {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    SHORT_LINK_HOSTS.contains(&host)
+}
+
+/// Follow a short share link to the canonical `open.spotify.com` URL.
+///
+/// The link is fetched over HTTP and redirects are followed until one points
+/// at `open.spotify.com`, which is returned without being requested. Share
+/// pages that redirect from the browser instead are searched for the
+/// canonical link. Returns [`Error::InvalidUrl`] if neither turns one up.
+///
+/// Example against a local stand-in for `spotify.link`:
+/// ```
+/// use std::io::{Read, Write};
+/// use std::net::TcpListener;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), rustifydl::Error> {
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let addr = listener.local_addr()?;
+/// std::thread::spawn(move || {
+///     let (mut stream, _) = listener.accept().unwrap();
+///     let _ = stream.read(&mut [0; 1024]);
+///     stream
+///         .write_all(
+///             b"HTTP/1.1 307 Temporary Redirect\r\n\
+///               Location: https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc\r\n\
+///               Content-Length: 0\r\n\r\n",
+///         )
+///         .unwrap();
+/// });
+///
+/// let url = rustifydl::resolve_short_link(&format!("http://{addr}/xyz")).await?;
+/// assert_eq!(url, "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn resolve_short_link(url: &str) -> Result<String> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.url().host_str() == Some("open.spotify.com") {
+                attempt.stop()
+            } else if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .map_err(|e| Error::InvalidUrl(format!("{url}: {e}")))?;
+    let response = client
+        .get(url.trim())
+        .send()
+        .await
+        .map_err(|e| Error::InvalidUrl(format!("{url}: {e}")))?;
+
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok());
+    if response.status().is_redirection()
+        && let Some(location) = location
+    {
+        return Ok(location.to_string());
+    }
+    if response.url().host_str() == Some("open.spotify.com") {
+        return Ok(response.url().to_string());
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let re = Regex::new(r#"https://open\.spotify\.com/[^"'\s<>]+"#).unwrap();
+    re.find(&body)
+        .map(|found| found.as_str().replace("&amp;", "&"))
+        .ok_or_else(|| Error::InvalidUrl(url.to_string()))
+}
+
+/// Parse a list of inputs, one per line, as read from `--input-file`.
+///
+/// Blank lines and `#` comments, whole-line or after whitespace, are skipped.
+///
+/// Example
+/// ```
+/// let inputs = rustifydl::resource::parse_input_list(
+///     "# road trip\n\
+///      https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M  # driving\n\
+///      \n\
+///      spotify:album:1DFixLWuPkv3KT3TnV35m3\n",
+/// );
+/// assert_eq!(
+///     inputs,
+///     [
+///         "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M",
+///         "spotify:album:1DFixLWuPkv3KT3TnV35m3",
+///     ]
+/// );
+/// ```
+pub fn parse_input_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            let line = line.trim();
+            match line.find(" #").or_else(|| line.find("\t#")) {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim()
+        })
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "37i9dQZF1DXcBWIGoYBM5M";
+
+    fn parsed(input: &str) -> (ResourceKind, String) {
+        let resource = SpotifyResource::parse(input).unwrap_or_else(|e| panic!("{input}: {e}"));
+        (resource.kind, resource.id)
+    }
+
+    #[test]
+    fn urls_with_locale_query_and_fragment() {
+        for input in [
+            format!("https://open.spotify.com/playlist/{ID}"),
+            format!("https://open.spotify.com/intl-de/playlist/{ID}"),
+            format!("https://open.spotify.com/intl-pt-BR/playlist/{ID}?si=abc&nd=1"),
+            format!("https://open.spotify.com/playlist/{ID}#section"),
+            format!("https://open.spotify.com/playlist/{ID}/?si=abc#section"),
+            format!("http://play.spotify.com/playlist/{ID}"),
+            format!("  https://open.spotify.com/embed/playlist/{ID}?utm_source=generator  "),
+            format!("https://open.spotify.com/user/someone/playlist/{ID}"),
+        ] {
+            assert_eq!(parsed(&input), (ResourceKind::Playlist, ID.to_string()));
+        }
+    }
+
+    #[test]
+    fn uris_including_legacy_user_playlists() {
+        assert_eq!(
+            parsed(&format!("spotify:album:{ID}")),
+            (ResourceKind::Album, ID.to_string())
+        );
+        assert_eq!(
+            parsed(&format!("spotify:user:someone:playlist:{ID}")),
+            (ResourceKind::Playlist, ID.to_string())
+        );
+        assert_eq!(
+            parsed(&format!("spotify:episode:{ID}")),
+            (ResourceKind::Episode, ID.to_string())
+        );
+        assert!(matches!(
+            SpotifyResource::parse(&format!("spotify:user:someone:{ID}")),
+            Err(Error::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn legacy_embed_widget() {
+        assert_eq!(
+            parsed(&format!(
+                "https://embed.spotify.com/?uri=spotify:track:{ID}"
+            )),
+            (ResourceKind::Track, ID.to_string())
+        );
+        assert_eq!(
+            parsed(&format!(
+                "https://embed.spotify.com/?theme=white&uri=spotify%3Auser%3Asomeone%3Aplaylist%3A{ID}"
+            )),
+            (ResourceKind::Playlist, ID.to_string())
+        );
+        assert!(SpotifyResource::parse("https://embed.spotify.com/?theme=white").is_err());
+    }
+
+    #[test]
+    fn kind_comes_from_the_path_not_the_id() {
+        // A playlist whose ID happens to contain "track" or "album".
+        let id = "0trackAlbum0playlist01";
+        assert_eq!(
+            parsed(&format!("https://open.spotify.com/playlist/{id}")),
+            (ResourceKind::Playlist, id.to_string())
+        );
+        assert_eq!(
+            parsed(&format!("spotify:playlist:{id}")),
+            (ResourceKind::Playlist, id.to_string())
+        );
+    }
+
+    #[test]
+    fn ids_must_be_22_base62_characters() {
+        for id in [
+            "37i9dQZF1DXcBWIGoYBM5",
+            "37i9dQZF1DXcBWIGoYBM5M0",
+            "37i9dQZF1DXcBWIGoYBM5-",
+            "37i9dQZF1DXcBWIGoYBM5é",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    SpotifyResource::parse(&format!("https://open.spotify.com/track/{id}")),
+                    Err(Error::InvalidUrl(_))
+                ),
+                "{id}"
+            );
+            assert!(
+                matches!(
+                    SpotifyResource::parse(&format!("spotify:track:{id}")),
+                    Err(Error::InvalidUrl(_))
+                ),
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn other_hosts_and_kinds_are_rejected() {
+        assert!(matches!(
+            SpotifyResource::parse(&format!("https://example.com/track/{ID}")),
+            Err(Error::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            SpotifyResource::parse(&format!("https://open.spotify.com/concert/{ID}")),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
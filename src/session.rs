@@ -0,0 +1,784 @@
+//! Shared, refreshable Spotify Web API session.
+//!
+//! One [`SpotifySession`] is owned by a download run and shared by every
+//! stage, so a run exchanges the client credentials at most once. The access
+//! token is also cached on disk (by default next to the CLI config) until it
+//! expires, so repeated runs skip the exchange entirely.
+//!
+//! [`SpotifySession::user`] builds a session that acts for a logged-in user
+//! instead, refreshing its token from a [`StoredLogin`](crate::auth::StoredLogin).
+//!
+//! Requests are spaced out to stay under a requests-per-second limit shared by
+//! every task using the session. Rate-limited (`429`) requests wait for the
+//! `Retry-After` the API asks for, and server errors and dropped connections
+//! are retried with jittered exponential backoff.
+//!
+//! Example
+//! ```no_run
+//! use std::sync::Arc;
+//! use rustifydl::{Downloader, DownloadOptions, SpotifySource};
+//! use rustifydl::session::SpotifySession;
+//!
+//! # async fn run(opts: DownloadOptions) -> rustifydl::Result<()> {
+//! let session = Arc::new(
+//!     SpotifySession::new(&opts.client_id, &opts.client_secret).token_cache(None),
+//! );
+//! let report = Downloader::new(opts, String::new())
+//!     .metadata_source(Arc::new(SpotifySource::with_session(session)))
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, warn};
+use rand::Rng;
+use reqwest::{Response, StatusCode, header::RETRY_AFTER};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use spotify_rs::model::Page;
+use tokio::sync::Mutex;
+
+use crate::{Error, Result, auth::StoredLogin};
+
+const API_URL: &str = "https://api.spotify.com/v1";
+const ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+
+/// Tokens this close to expiry are treated as expired, so they don't run out mid-request.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Longest `Retry-After` waited out; a longer ban fails the request instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// Upper bound for the backoff between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// An access token and when it stops being valid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedToken {
+    /// Client the token was issued to; a cache for other credentials is ignored.
+    client_id: String,
+    access_token: String,
+    /// Expiry as seconds since the Unix epoch.
+    expires_at: u64,
+}
+
+impl CachedToken {
+    fn is_fresh(&self) -> bool {
+        unix_now() + EXPIRY_MARGIN.as_secs() < self.expires_at
+    }
+}
+
+/// Response of the token endpoint. Spotify may rotate the refresh token on a
+/// refresh, in which case the new one is included.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+/// A user login the session refreshes its token with.
+struct UserGrant {
+    refresh_token: std::sync::Mutex<String>,
+    /// Where a rotated refresh token is saved.
+    login_store: Option<PathBuf>,
+}
+
+/// Error body returned by the Web API.
+#[derive(Deserialize)]
+struct ApiError {
+    error: ApiErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+}
+
+/// Authenticated access to the Spotify Web API (client credentials flow, or
+/// a refresh token for [user sessions](Self::user)).
+///
+/// Requests from concurrent tasks share one token; it is fetched on first use
+/// and refreshed when it is about to expire or the API rejects it.
+pub struct SpotifySession {
+    http: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+    user: Option<UserGrant>,
+    api_url: String,
+    accounts_url: String,
+    token_cache: Option<PathBuf>,
+    token: Mutex<Option<CachedToken>>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    /// Minimum time between two requests; zero disables the limit.
+    request_interval: Duration,
+    /// Earliest time the next request may be sent.
+    next_request: Mutex<Instant>,
+}
+
+impl SpotifySession {
+    /// Create a session for the given API credentials, caching the token in
+    /// [`default_token_cache`]. No request is made until the first lookup.
+    pub fn new(client_id: impl Into<String>, client_secret: impl Into<String>) -> Self {
+        SpotifySession {
+            http: reqwest::Client::new(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            user: None,
+            api_url: API_URL.to_string(),
+            accounts_url: ACCOUNTS_URL.to_string(),
+            token_cache: default_token_cache(),
+            token: Mutex::new(None),
+            max_retries: 5,
+            retry_backoff: Duration::from_millis(500),
+            request_interval: Duration::from_secs(1) / 10,
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Create a session acting for the user of `login`, caching the token in
+    /// [`default_user_token_cache`]. If Spotify rotates the refresh token,
+    /// the new one is saved to [`StoredLogin::default_path`]; see
+    /// [`login_store`](Self::login_store).
+    pub fn user(login: StoredLogin) -> Self {
+        SpotifySession {
+            user: Some(UserGrant {
+                refresh_token: std::sync::Mutex::new(login.refresh_token),
+                login_store: StoredLogin::default_path(),
+            }),
+            token_cache: default_user_token_cache(),
+            ..SpotifySession::new(login.client_id, String::new())
+        }
+    }
+
+    /// Where a user session saves a rotated refresh token; `None` keeps it in
+    /// memory only. Has no effect on client credentials sessions.
+    pub fn login_store(mut self, path: Option<PathBuf>) -> Self {
+        if let Some(user) = &mut self.user {
+            user.login_store = path;
+        }
+        self
+    }
+
+    /// Whether this session acts for a logged-in user.
+    pub fn is_user(&self) -> bool {
+        self.user.is_some()
+    }
+
+    /// Where the access token is cached between runs; `None` keeps it in memory only.
+    pub fn token_cache(mut self, path: Option<PathBuf>) -> Self {
+        self.token_cache = path;
+        self
+    }
+
+    /// Base URL of the Web API, `https://api.spotify.com/v1` by default.
+    /// Mostly useful for pointing tests at a local server.
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        self.api_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Base URL of the accounts service, `https://accounts.spotify.com` by default.
+    pub fn accounts_url(mut self, url: impl Into<String>) -> Self {
+        self.accounts_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// How often a rate-limited or failed request is retried, 5 by default.
+    /// Covers API lookups and token exchanges alike.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Backoff before the first retry, doubled for every further one. The
+    /// actual wait is randomly between half and all of it, so concurrent
+    /// tasks don't retry in lockstep. 500 ms by default.
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Most requests sent per second, across every task sharing the session.
+    /// 10 by default; 0 disables the limit.
+    pub fn requests_per_second(mut self, requests: u32) -> Self {
+        self.request_interval = if requests == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(1) / requests
+        };
+        self
+    }
+
+    /// A valid access token, from memory, the disk cache or a new exchange,
+    /// in that order.
+    pub async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(cached) = token.as_ref().filter(|cached| cached.is_fresh()) {
+            return Ok(cached.access_token.clone());
+        }
+        if let Some(cached) = self.read_cache() {
+            debug!("Using cached Spotify token");
+            let access_token = cached.access_token.clone();
+            *token = Some(cached);
+            return Ok(access_token);
+        }
+
+        let fresh = self.request_token().await?;
+        self.write_cache(&fresh);
+        let access_token = fresh.access_token.clone();
+        *token = Some(fresh);
+        Ok(access_token)
+    }
+
+    /// Forget the current token, in memory and on disk.
+    pub async fn invalidate(&self) {
+        *self.token.lock().await = None;
+        if let Some(path) = &self.token_cache {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// `GET` a Web API endpoint and deserialize the JSON response.
+    ///
+    /// `path` is either relative to the API base (`"tracks/<id>"`) or an
+    /// absolute URL, such as a page's `next` link. A rejected token is
+    /// refreshed and the request retried once. Rate-limited requests, server
+    /// errors and network failures are retried up to
+    /// [`max_retries`](Self::max_retries) times.
+    ///
+    /// ```
+    /// use std::{sync::atomic::{AtomicUsize, Ordering}, sync::Arc, time::Duration};
+    /// use rustifydl::session::SpotifySession;
+    /// use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> rustifydl::Result<()> {
+    /// // A mock API that rate-limits the first two lookups.
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let base = format!("http://{}", listener.local_addr()?);
+    /// let lookups = Arc::new(AtomicUsize::new(0));
+    /// let seen = Arc::clone(&lookups);
+    /// tokio::spawn(async move {
+    ///     loop {
+    ///         let (mut stream, _) = listener.accept().await.unwrap();
+    ///         let mut buf = [0; 4096];
+    ///         let n = stream.read(&mut buf).await.unwrap();
+    ///         let request = String::from_utf8_lossy(&buf[..n]);
+    ///         let (status, body) = if request.starts_with("POST /api/token") {
+    ///             ("200 OK", r#"{"access_token":"t","expires_in":3600}"#)
+    ///         } else if seen.fetch_add(1, Ordering::SeqCst) < 2 {
+    ///             ("429 Too Many Requests\r\nRetry-After: 0", "")
+    ///         } else {
+    ///             ("200 OK", r#"{"name":"Intro"}"#)
+    ///         };
+    ///         let response = format!(
+    ///             "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+    ///             body.len()
+    ///         );
+    ///         stream.write_all(response.as_bytes()).await.unwrap();
+    ///     }
+    /// });
+    ///
+    /// let session = SpotifySession::new("id", "secret")
+    ///     .token_cache(None)
+    ///     .api_url(&base)
+    ///     .accounts_url(&base)
+    ///     .retry_backoff(Duration::from_millis(10));
+    /// let track: serde_json::Value = session.get("tracks/x").await?;
+    /// assert_eq!(track["name"], "Intro");
+    /// assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.api_url, path.trim_start_matches('/'))
+        };
+
+        let mut refreshed = false;
+        let mut attempt = 0;
+        let response = loop {
+            let access_token = self.access_token().await?;
+            self.throttle().await;
+            let sent = self.http.get(&url).bearer_auth(access_token).send().await;
+            if let Ok(response) = &sent
+                && response.status() == StatusCode::UNAUTHORIZED
+                && !refreshed
+            {
+                debug!("Spotify rejected the access token, refreshing");
+                refreshed = true;
+                self.invalidate().await;
+                continue;
+            }
+
+            if !self.wait_to_retry(&sent, &mut attempt).await {
+                break sent.map_err(request_error)?;
+            }
+        };
+
+        let status = response.status();
+        let body = response.text().await.map_err(request_error)?;
+        if !status.is_success() {
+            let message = serde_json::from_str::<ApiError>(&body)
+                .map(|e| e.error.message)
+                .unwrap_or(body);
+            return Err(Error::Spotify {
+                status: Some(status.as_u16()),
+                message,
+            });
+        }
+        serde_json::from_str(&body).map_err(|e| Error::Spotify {
+            status: Some(status.as_u16()),
+            message: format!("Unexpected response from {url}: {e}"),
+        })
+    }
+
+    /// Collect the items of `page` and every page after it.
+    pub async fn get_all<T: Clone + DeserializeOwned>(&self, mut page: Page<T>) -> Result<Vec<T>> {
+        let mut items = Vec::with_capacity(page.total as usize);
+        loop {
+            items.extend(page.items.into_iter().flatten());
+            match page.next {
+                Some(next) => page = self.get(&next).await?,
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// Wait for this request's turn under the requests-per-second limit.
+    async fn throttle(&self) {
+        let slot = {
+            let mut next = self.next_request.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.request_interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    /// Hold back every request for `wait`.
+    async fn hold_off(&self, wait: Duration) {
+        let mut next = self.next_request.lock().await;
+        *next = (*next).max(Instant::now() + wait);
+    }
+
+    /// Whether a request that got `sent` back is worth retrying. If so, waits
+    /// out the backoff, or holds back every request for the `Retry-After` of
+    /// a `429`, and counts the attempt.
+    async fn wait_to_retry(&self, sent: &reqwest::Result<Response>, attempt: &mut u32) -> bool {
+        let wait = match sent {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                Some(retry_after(response).unwrap_or_else(|| self.backoff(*attempt)))
+            }
+            Ok(response) if response.status().is_server_error() => Some(self.backoff(*attempt)),
+            Ok(_) => None,
+            Err(_) => Some(self.backoff(*attempt)),
+        };
+        let Some(wait) =
+            wait.filter(|wait| *attempt < self.max_retries && *wait <= MAX_RETRY_AFTER)
+        else {
+            return false;
+        };
+        *attempt += 1;
+        match sent {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                warn!("Spotify rate limit hit, waiting {wait:?}");
+                // Everyone waits, not only this request; the retry's throttle
+                // does the waiting.
+                self.hold_off(wait).await;
+            }
+            Ok(response) => {
+                debug!(
+                    "Spotify returned {}, retrying in {wait:?}",
+                    response.status()
+                );
+                tokio::time::sleep(wait).await;
+            }
+            Err(e) => {
+                debug!("Request to Spotify failed ({e}), retrying in {wait:?}");
+                tokio::time::sleep(wait).await;
+            }
+        }
+        true
+    }
+
+    /// Jittered exponential backoff before retry number `attempt + 1`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .retry_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_BACKOFF);
+        backoff.mul_f64(rand::rng().random_range(0.5..=1.0))
+    }
+
+    /// Exchange the client credentials or refresh token for a new access
+    /// token, retried like [`get`](Self::get).
+    async fn request_token(&self) -> Result<CachedToken> {
+        if self.user.is_none()
+            && (self.client_id.trim().is_empty() || self.client_secret.trim().is_empty())
+        {
+            return Err(Error::SpotifyAuth(
+                "Spotify client ID and secret are required".to_string(),
+            ));
+        }
+        let url = format!("{}/api/token", self.accounts_url);
+
+        let mut attempt = 0;
+        let response = loop {
+            let request = self.http.post(&url);
+            let request = match &self.user {
+                Some(user) => {
+                    let refresh_token = user.refresh_token.lock().unwrap().clone();
+                    request.form(&[
+                        ("grant_type", "refresh_token"),
+                        ("refresh_token", refresh_token.as_str()),
+                        ("client_id", self.client_id.as_str()),
+                    ])
+                }
+                None => request
+                    .basic_auth(&self.client_id, Some(&self.client_secret))
+                    .form(&[("grant_type", "client_credentials")]),
+            };
+            self.throttle().await;
+            let sent = request.send().await;
+            if !self.wait_to_retry(&sent, &mut attempt).await {
+                break sent.map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+            }
+        };
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+        if !status.is_success() {
+            return Err(Error::SpotifyAuth(format!("{status}: {body}")));
+        }
+        let token: TokenResponse =
+            serde_json::from_str(&body).map_err(|e| Error::SpotifyAuth(e.to_string()))?;
+        debug!("Got a new Spotify token, valid for {}s", token.expires_in);
+        if let (Some(user), Some(rotated)) = (&self.user, token.refresh_token) {
+            self.store_refresh_token(user, rotated);
+        }
+        Ok(CachedToken {
+            client_id: self.client_id.clone(),
+            access_token: token.access_token,
+            expires_at: unix_now() + token.expires_in,
+        })
+    }
+
+    fn read_cache(&self) -> Option<CachedToken> {
+        let content = fs::read_to_string(self.token_cache.as_ref()?).ok()?;
+        serde_json::from_str::<CachedToken>(&content)
+            .ok()
+            .filter(|cached| cached.client_id == self.client_id && cached.is_fresh())
+    }
+
+    /// Remember a rotated refresh token; the old one may stop working.
+    fn store_refresh_token(&self, user: &UserGrant, refresh_token: String) {
+        *user.refresh_token.lock().unwrap() = refresh_token.clone();
+        let Some(path) = &user.login_store else {
+            return;
+        };
+        let login = StoredLogin {
+            client_id: self.client_id.clone(),
+            refresh_token,
+        };
+        if let Err(e) = login.save(path) {
+            warn!(
+                "Couldn't save the new Spotify login to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    /// Best effort: a cache that can't be written only costs a token exchange next run.
+    fn write_cache(&self, token: &CachedToken) {
+        let Some(path) = &self.token_cache else {
+            return;
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                write_private(
+                    path,
+                    serde_json::to_string(token).unwrap_or_default().as_bytes(),
+                )
+            });
+        if let Err(e) = written {
+            warn!("Couldn't cache Spotify token in {}: {e}", path.display());
+        }
+    }
+}
+
+/// Write `contents` to `path`, readable only by the current user on Unix.
+///
+/// The file is created with mode 0600 rather than restricted after writing,
+/// so the token it holds is never readable by others, even briefly.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // The mode only applies to new files; tighten one left by an older version.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)
+}
+
+/// `<config dir>/RustifyDL/spotify_token.json`, next to the CLI's `config.toml`.
+pub fn default_token_cache() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("RustifyDL").join("spotify_token.json"))
+}
+
+/// `<config dir>/RustifyDL/spotify_user_token.json`, the token cache of
+/// [user sessions](SpotifySession::user). Kept apart from the client
+/// credentials cache, since both are issued to the same client.
+pub fn default_user_token_cache() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("RustifyDL").join("spotify_user_token.json"))
+}
+
+/// Percent-encode `value` for use in a query string, leaving only RFC 3986
+/// unreserved characters as they are.
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The wait a `429` response asks for, from its `Retry-After` seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    seconds.trim().parse().ok().map(Duration::from_secs)
+}
+
+fn request_error(e: reqwest::Error) -> Error {
+    Error::Spotify {
+        status: e.status().map(|status| status.as_u16()),
+        message: e.to_string(),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serve `respond(request, token requests so far, lookups so far)` on a
+    /// local port, and count the token requests and lookups it gets.
+    async fn mock_spotify(
+        respond: impl Fn(&str, usize, usize) -> (&'static str, String) + Send + 'static,
+    ) -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let tokens = Arc::new(AtomicUsize::new(0));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let (token_count, lookup_count) = (Arc::clone(&tokens), Arc::clone(&lookups));
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let (status, body) = if request.starts_with("POST /api/token") {
+                    let seen = token_count.fetch_add(1, Ordering::SeqCst);
+                    respond(&request, seen, lookup_count.load(Ordering::SeqCst))
+                } else {
+                    let seen = lookup_count.fetch_add(1, Ordering::SeqCst);
+                    respond(&request, token_count.load(Ordering::SeqCst), seen)
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (base, tokens, lookups)
+    }
+
+    fn session(base: &str) -> SpotifySession {
+        SpotifySession::new("id", "secret")
+            .token_cache(None)
+            .api_url(base)
+            .accounts_url(base)
+            .retry_backoff(Duration::from_millis(1))
+            .requests_per_second(0)
+    }
+
+    fn token(access_token: &str) -> (&'static str, String) {
+        (
+            "200 OK",
+            format!(r#"{{"access_token":"{access_token}","expires_in":3600}}"#),
+        )
+    }
+
+    #[tokio::test]
+    async fn long_retry_after_fails_the_request() {
+        let (base, _, lookups) = mock_spotify(|request, _, _| {
+            if request.starts_with("POST") {
+                token("t")
+            } else {
+                ("429 Too Many Requests\r\nRetry-After: 3600", String::new())
+            }
+        })
+        .await;
+
+        let result = session(&base).get::<serde_json::Value>("tracks/x").await;
+        assert!(matches!(
+            result,
+            Err(Error::Spotify {
+                status: Some(429),
+                ..
+            })
+        ));
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn server_errors_fail_once_the_retries_run_out() {
+        let (base, _, lookups) = mock_spotify(|request, _, _| {
+            if request.starts_with("POST") {
+                token("t")
+            } else {
+                ("503 Service Unavailable", String::new())
+            }
+        })
+        .await;
+
+        let result = session(&base)
+            .max_retries(2)
+            .get::<serde_json::Value>("tracks/x")
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Spotify {
+                status: Some(503),
+                ..
+            })
+        ));
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn rejected_tokens_are_refreshed_once() {
+        let (base, tokens, lookups) = mock_spotify(|request, tokens, _| {
+            if request.starts_with("POST") {
+                token(&format!("t{tokens}"))
+            } else if request.contains("Bearer t0") {
+                ("401 Unauthorized", String::new())
+            } else {
+                ("200 OK", r#"{"name":"Intro"}"#.to_string())
+            }
+        })
+        .await;
+
+        let track: serde_json::Value = session(&base).get("tracks/x").await.unwrap();
+        assert_eq!(track["name"], "Intro");
+        assert_eq!(tokens.load(Ordering::SeqCst), 2);
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+        // A fresh token that is rejected too isn't refreshed again.
+        let (base, tokens, lookups) = mock_spotify(|request, tokens, _| {
+            if request.starts_with("POST") {
+                token(&format!("t{tokens}"))
+            } else {
+                ("401 Unauthorized", String::new())
+            }
+        })
+        .await;
+        let result = session(&base).get::<serde_json::Value>("tracks/x").await;
+        assert!(matches!(
+            result,
+            Err(Error::Spotify {
+                status: Some(401),
+                ..
+            })
+        ));
+        assert_eq!(tokens.load(Ordering::SeqCst), 2);
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn token_exchanges_are_retried() {
+        let (base, tokens, _) = mock_spotify(|request, tokens, _| match tokens {
+            _ if !request.starts_with("POST") => ("200 OK", r#"{"name":"Intro"}"#.to_string()),
+            0 => ("429 Too Many Requests\r\nRetry-After: 0", String::new()),
+            1 => ("502 Bad Gateway", String::new()),
+            _ => token("t"),
+        })
+        .await;
+
+        let track: serde_json::Value = session(&base).get("tracks/x").await.unwrap();
+        assert_eq!(track["name"], "Intro");
+        assert_eq!(tokens.load(Ordering::SeqCst), 3);
+
+        // Rate limits longer than MAX_RETRY_AFTER fail the exchange.
+        let (base, tokens, _) =
+            mock_spotify(|_, _, _| ("429 Too Many Requests\r\nRetry-After: 3600", String::new()))
+                .await;
+        let result = session(&base).access_token().await;
+        assert!(matches!(result, Err(Error::SpotifyAuth(_))));
+        assert_eq!(tokens.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_created_and_kept_at_0600() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("rustifydl-private-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("token.json");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        write_private(&path, b"first").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, b"second").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
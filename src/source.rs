@@ -0,0 +1,300 @@
+//! Catalog abstraction used to resolve what to download and how to tag it.
+//!
+//! The pipeline only sees the crate-owned [`TrackInfo`] and [`AlbumInfo`]
+//! models, handed out by a [`MetadataSource`]. [`SpotifySource`](crate::spotify::SpotifySource)
+//! is the default implementation; other catalogs or fixture-backed sources for
+//! offline tests plug in through [`Downloader::metadata_source`](crate::Downloader::metadata_source).
+//!
+//! Example
+//! ```no_run
+//! use std::{collections::HashMap, sync::Arc};
+//! use rustifydl::{DownloadOptions, Downloader, Error, Result};
+//! use rustifydl::source::{AlbumInfo, MetadataSource, TrackInfo};
+//!
+//! /// Serves tracks from memory, e.g. loaded from a JSON fixture.
+//! struct Fixtures {
+//!     tracks: HashMap<String, TrackInfo>,
+//! }
+//!
+//! #[async_trait::async_trait]
+//! impl MetadataSource for Fixtures {
+//!     async fn track(&self, id: &str) -> Result<TrackInfo> {
+//!         self.tracks.get(id).cloned().ok_or_else(|| Error::Spotify {
+//!             status: Some(404),
+//!             message: format!("no fixture for {id}"),
+//!         })
+//!     }
+//!     async fn album_tracks(&self, id: &str) -> Result<Vec<TrackInfo>> {
+//!         Ok(self.tracks.values().filter(|t| t.album.id == id).cloned().collect())
+//!     }
+//!     async fn playlist_tracks(&self, _id: &str) -> Result<Vec<TrackInfo>> {
+//!         Ok(self.tracks.values().cloned().collect())
+//!     }
+//!     async fn album(&self, id: &str) -> Result<AlbumInfo> {
+//!         self.tracks
+//!             .values()
+//!             .find(|t| t.album.id == id)
+//!             .map(|t| t.album.clone())
+//!             .ok_or_else(|| Error::Unsupported(format!("no fixture album {id}")))
+//!     }
+//! }
+//!
+//! # async fn run(opts: DownloadOptions, fixtures: Fixtures) -> Result<()> {
+//! let report = Downloader::new(opts, String::new())
+//!     .metadata_source(Arc::new(fixtures))
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result, options::ReleaseType};
+
+/// Album details needed for folder names and tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlbumInfo {
+    /// Catalog ID of the album.
+    pub id: String,
+    /// Album title.
+    pub name: String,
+    /// Album artist names.
+    pub artists: Vec<String>,
+    /// Genres; may be empty when the catalog has none.
+    pub genres: Vec<String>,
+    /// Record label, if known.
+    pub label: Option<String>,
+    /// Release date as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub release_date: String,
+    /// Number of tracks on the album.
+    pub total_tracks: u32,
+    /// URL of the largest cover image.
+    pub cover_url: Option<String>,
+}
+
+impl AlbumInfo {
+    /// Release year parsed from [`release_date`](Self::release_date).
+    pub fn year(&self) -> Option<u32> {
+        self.release_date.get(..4)?.parse().ok()
+    }
+}
+
+/// Whether a [`TrackInfo`] is a song or a podcast episode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackKind {
+    #[default]
+    Song,
+    /// A podcast episode. Its [`album`](TrackInfo::album) is the show, with
+    /// the publisher as artist and the episode's release date.
+    Episode,
+}
+
+/// Whether a playlist entry can be downloaded, and if not, why.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    /// A song the catalog has.
+    #[default]
+    Downloadable,
+    /// A podcast episode, downloaded into its show's folder.
+    Episode,
+    /// A file the owner added from their own disk; the catalog has no audio
+    /// or metadata for it beyond what the app shows.
+    LocalFile,
+    /// Not playable in the requested market. Holds the reason, e.g.
+    /// `"unavailable in market"`.
+    Unavailable(String),
+    /// The entry is still in the playlist but its track was removed from
+    /// the catalog.
+    Removed,
+}
+
+impl EntryStatus {
+    /// Whether the entry is downloaded: songs and episodes.
+    pub fn is_downloadable(&self) -> bool {
+        matches!(self, EntryStatus::Downloadable | EntryStatus::Episode)
+    }
+
+    /// Short name of the status, e.g. `"local_file"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryStatus::Downloadable => "downloadable",
+            EntryStatus::Episode => "episode",
+            EntryStatus::LocalFile => "local_file",
+            EntryStatus::Unavailable(_) => "unavailable",
+            EntryStatus::Removed => "removed",
+        }
+    }
+}
+
+impl fmt::Display for EntryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryStatus::Downloadable => write!(f, "downloadable"),
+            EntryStatus::Episode => write!(f, "podcast episode"),
+            EntryStatus::LocalFile => write!(f, "local file, not on Spotify"),
+            EntryStatus::Unavailable(reason) => write!(f, "{reason}"),
+            EntryStatus::Removed => write!(f, "removed from Spotify"),
+        }
+    }
+}
+
+/// One track as resolved from the catalog.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackInfo {
+    /// Catalog ID of the track.
+    pub id: String,
+    /// Track title.
+    pub name: String,
+    /// Artist names, in credit order.
+    pub artists: Vec<String>,
+    /// Catalog IDs of [`artists`](Self::artists), in the same order; empty
+    /// when the source doesn't provide them.
+    #[serde(default)]
+    pub artist_ids: Vec<String>,
+    /// Album the track belongs to, with every field filled in: tagging uses
+    /// it as is, without further lookups.
+    pub album: AlbumInfo,
+    /// Disc number, starting at 1.
+    pub disc_number: u32,
+    /// Track number on the disc, starting at 1.
+    pub track_number: u32,
+    /// Duration in milliseconds.
+    pub duration_ms: u32,
+    /// International Standard Recording Code, if known.
+    pub isrc: Option<String>,
+    /// Song or podcast episode.
+    #[serde(default)]
+    pub kind: TrackKind,
+    /// Episode description, written as the comment tag.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Whether the entry can be downloaded. Entries that can't are kept in
+    /// the plan so the report can name them, but nothing is downloaded.
+    #[serde(default)]
+    pub status: EntryStatus,
+}
+
+impl TrackInfo {
+    /// Artist names joined with `", "`.
+    pub fn artist_names(&self) -> String {
+        self.artists.join(", ")
+    }
+
+    /// `"Artists - Title"`, the name used for search and file names. Just
+    /// the title when there are no artists, as for removed entries.
+    pub fn display_name(&self) -> String {
+        if self.artists.is_empty() {
+            return self.name.clone();
+        }
+        format!("{} - {}", self.artist_names(), self.name)
+    }
+
+    /// Whether this is a podcast episode.
+    pub fn is_episode(&self) -> bool {
+        self.kind == TrackKind::Episode
+    }
+}
+
+/// A playlist in a user's library.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistInfo {
+    /// Catalog ID of the playlist.
+    pub id: String,
+    /// Playlist title.
+    pub name: String,
+    /// Display name of the owner, or their ID if they have none.
+    pub owner: String,
+    /// Number of entries, including episodes and local files.
+    pub total_tracks: u32,
+}
+
+/// A catalog that can resolve tracks, albums and playlists.
+///
+/// Implementations are shared between concurrent download tasks. Returned
+/// tracks carry full [`AlbumInfo`]s; batch the album lookups where the
+/// catalog allows it.
+#[async_trait]
+pub trait MetadataSource: Send + Sync {
+    /// Look up a single track.
+    async fn track(&self, id: &str) -> Result<TrackInfo>;
+
+    /// Look up several tracks. IDs the catalog doesn't know are left out.
+    /// The default looks them up one at a time, failing on the first error.
+    async fn tracks(&self, ids: &[String]) -> Result<Vec<TrackInfo>> {
+        let mut tracks = Vec::with_capacity(ids.len());
+        for id in ids {
+            tracks.push(self.track(id).await?);
+        }
+        Ok(tracks)
+    }
+
+    /// All tracks of an album, in album order.
+    async fn album_tracks(&self, id: &str) -> Result<Vec<TrackInfo>>;
+
+    /// All tracks of a playlist, in playlist order.
+    async fn playlist_tracks(&self, id: &str) -> Result<Vec<TrackInfo>>;
+
+    /// Full album details.
+    async fn album(&self, id: &str) -> Result<AlbumInfo>;
+
+    /// An artist's releases of the given types, grouped by type in the order
+    /// of `types`. Sources without artist support return [`Error::Unsupported`].
+    async fn artist_albums(&self, id: &str, types: &[ReleaseType]) -> Result<Vec<AlbumInfo>> {
+        let _ = types;
+        Err(Error::Unsupported(format!(
+            "this metadata source can't list the releases of artist {id}"
+        )))
+    }
+
+    /// A single podcast episode. Sources without podcasts return
+    /// [`Error::Unsupported`].
+    async fn episode(&self, id: &str) -> Result<TrackInfo> {
+        Err(Error::Unsupported(format!(
+            "this metadata source has no podcast episode {id}"
+        )))
+    }
+
+    /// All episodes of a podcast show, newest first.
+    async fn show_episodes(&self, id: &str) -> Result<Vec<TrackInfo>> {
+        Err(Error::Unsupported(format!(
+            "this metadata source has no podcast show {id}"
+        )))
+    }
+
+    /// Up to `limit` tracks matching a free-text query, best match first.
+    /// Sources without search return [`Error::Unsupported`].
+    async fn search_tracks(&self, query: &str, limit: usize) -> Result<Vec<TrackInfo>> {
+        let _ = limit;
+        Err(Error::Unsupported(format!(
+            "this metadata source can't search for \"{query}\""
+        )))
+    }
+
+    /// The logged-in user's Liked Songs, most recently added first. Sources
+    /// without user libraries return [`Error::Unsupported`].
+    async fn liked_tracks(&self) -> Result<Vec<TrackInfo>> {
+        Err(Error::Unsupported(
+            "this metadata source has no Liked Songs".to_string(),
+        ))
+    }
+
+    /// The logged-in user's saved albums, most recently added first.
+    async fn saved_albums(&self) -> Result<Vec<AlbumInfo>> {
+        Err(Error::Unsupported(
+            "this metadata source has no saved albums".to_string(),
+        ))
+    }
+
+    /// Playlists the logged-in user owns or follows, in library order.
+    async fn user_playlists(&self) -> Result<Vec<PlaylistInfo>> {
+        Err(Error::Unsupported(
+            "this metadata source has no user playlists".to_string(),
+        ))
+    }
+}
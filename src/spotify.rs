@@ -1,154 +1,804 @@
 //! Spotify helpers for resolving tracks, albums, and playlists.
 //!
-//! Input: a Spotify ID string and authenticated client credentials (provided
-//! via [`DownloadOptions`]).
-//! Output: a `HashMap<String, Track>` keyed by a human-friendly display name,
+//! With a [stored login](crate::auth::StoredLogin), the logged-in user's
+//! library can be fetched too: Liked Songs, saved albums and their playlists.
+//!
+//! Input: a Spotify ID string and a [`MetadataSource`], usually a
+//! [`SpotifySource`] built from the client credentials in [`DownloadOptions`].
+//! Output: an ordered `Vec<PlannedTrack>` whose output names are human-friendly,
 //! e.g. `"Artists - Title"` or with dupes, if there are two of the same file `"Artists - Album - Title"`.
 
-use crate::DownloadOptions;
-use log::info;
-use spotify_rs::model::track::Track;
-use spotify_rs::{ClientCredsClient, model::PlayableItem};
-use std::collections::HashMap;
+use crate::{
+    DownloadOptions, Error, Result,
+    auth::StoredLogin,
+    options::ReleaseType,
+    plan::{PlannedTrack, plan_albums, plan_playlists, plan_tracks},
+    session::{SpotifySession, percent_encode},
+    source::{AlbumInfo, EntryStatus, MetadataSource, PlaylistInfo, TrackInfo, TrackKind},
+};
+use async_trait::async_trait;
+use log::{debug, info};
+use serde::{Deserialize, de::DeserializeOwned};
+use spotify_rs::model::{
+    Image, Page, RestrictionReason,
+    album::{Album, SavedAlbum, SimplifiedAlbum},
+    artist::Artist,
+    playlist::SimplifiedPlaylist,
+    track::{SavedTrack, Track},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Most IDs the "several tracks", "several albums" and "several artists"
+/// endpoints accept per request.
+const TRACKS_PER_REQUEST: usize = 50;
+const ALBUMS_PER_REQUEST: usize = 20;
+const ARTISTS_PER_REQUEST: usize = 50;
+
+/// Market for podcast lookups when not logged in. Spotify treats episodes
+/// and shows as unavailable when a client credentials request names none.
+const PODCAST_MARKET: &str = "US";
+
+// spotify-rs models shows' `copyrights` as strings and can't parse shows that
+// have any, so episodes, shows and the playlist entries that may hold them
+// are read into these instead.
+
+#[derive(Clone, Deserialize)]
+struct PlaylistObject {
+    name: String,
+    tracks: Page<PlaylistEntry>,
+}
+
+#[derive(Clone, Deserialize)]
+struct PlaylistEntry {
+    track: Option<PlaylistItem>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum PlaylistItem {
+    Track(Box<Track>),
+    Episode(Box<EpisodeObject>),
+    /// Local files come as track objects without IDs, which [`Track`] rejects.
+    Local(LocalTrack),
+}
+
+#[derive(Clone, Deserialize)]
+struct LocalTrack {
+    uri: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    artists: Vec<LocalName>,
+    album: Option<LocalName>,
+    #[serde(default)]
+    duration_ms: u32,
+}
+
+#[derive(Clone, Deserialize)]
+struct LocalName {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+struct EpisodeObject {
+    id: String,
+    name: String,
+    description: String,
+    duration_ms: u32,
+    release_date: String,
+    #[serde(default)]
+    images: Vec<Image>,
+    /// Missing when listed under the show itself.
+    show: Option<ShowSummary>,
+}
+
+#[derive(Clone, Deserialize)]
+struct ShowSummary {
+    id: String,
+    name: String,
+    publisher: String,
+    #[serde(default)]
+    images: Vec<Image>,
+    total_episodes: u32,
+}
+
+#[derive(Clone, Deserialize)]
+struct ShowObject {
+    #[serde(flatten)]
+    summary: ShowSummary,
+    episodes: Page<EpisodeObject>,
+}
+
+/// [`MetadataSource`] backed by the Spotify Web API (client credentials flow).
+///
+/// Every lookup goes through one shared [`SpotifySession`], so the token is
+/// exchanged once per run (or not at all while the cached one is valid).
+/// Library lookups, and playlists when logged in, use a second
+/// [user session](SpotifySession::user) instead.
+pub struct SpotifySource {
+    session: Arc<SpotifySession>,
+    user_session: Option<Arc<SpotifySession>>,
+    market: Option<String>,
+}
+
+impl SpotifySource {
+    /// Create a source for the given API credentials. No request is made
+    /// until the first lookup.
+    pub fn new(client_id: impl Into<String>, client_secret: impl Into<String>) -> Self {
+        SpotifySource::with_session(Arc::new(SpotifySession::new(client_id, client_secret)))
+    }
+
+    /// Build a source from the credentials in `options`. If a login for the
+    /// same client is stored at [`StoredLogin::default_path`], it is used for
+    /// library lookups.
+    pub fn from_options(options: &DownloadOptions) -> Self {
+        let source = SpotifySource::new(&options.client_id, &options.client_secret)
+            .market(options.market.clone());
+        match StoredLogin::default_path()
+            .and_then(|path| StoredLogin::load(&path, &options.client_id))
+        {
+            Some(login) => {
+                debug!("Using the stored Spotify login");
+                source.with_user_session(Arc::new(SpotifySession::user(login)))
+            }
+            None => source,
+        }
+    }
+
+    /// Use an existing session, e.g. one shared with other sources or
+    /// pointed at a test server.
+    pub fn with_session(session: Arc<SpotifySession>) -> Self {
+        SpotifySource {
+            session,
+            user_session: None,
+            market: None,
+        }
+    }
+
+    /// Look tracks, albums and playlists up as available in `market`, an
+    /// ISO 3166-1 alpha-2 country code. Spotify then relinks tracks to the
+    /// version playable there and flags the ones that aren't.
+    pub fn market(mut self, market: Option<String>) -> Self {
+        self.market = market;
+        self
+    }
+
+    /// Act for a logged-in user, for Liked Songs, saved albums and private
+    /// playlists.
+    pub fn with_user_session(mut self, session: Arc<SpotifySession>) -> Self {
+        self.user_session = Some(session);
+        self
+    }
+
+    /// The session this source makes its requests with.
+    pub fn session(&self) -> &Arc<SpotifySession> {
+        &self.session
+    }
+
+    /// The session for library lookups, if logged in.
+    pub fn user_session(&self) -> Option<&Arc<SpotifySession>> {
+        self.user_session.as_ref()
+    }
+
+    /// `path` with the configured market added to its query, if any.
+    fn with_market(&self, path: &str) -> String {
+        match &self.market {
+            Some(market) if path.contains('?') => format!("{path}&market={market}"),
+            Some(market) => format!("{path}?market={market}"),
+            None => path.to_string(),
+        }
+    }
+
+    /// Replace the album each song carries with the full album details, so
+    /// tagging needs no lookups. Albums are fetched in batches; episodes and
+    /// entries that can't be downloaded are left as they are.
+    async fn with_full_albums(&self, mut tracks: Vec<TrackInfo>) -> Result<Vec<TrackInfo>> {
+        let mut ids: Vec<String> = Vec::new();
+        for track in tracks
+            .iter()
+            .filter(|track| track.status == EntryStatus::Downloadable)
+        {
+            if !ids.contains(&track.album.id) {
+                ids.push(track.album.id.clone());
+            }
+        }
+        let albums: Vec<Album> = get_several(
+            &self.session,
+            "albums",
+            &ids,
+            ALBUMS_PER_REQUEST,
+            self.market.as_deref(),
+        )
+        .await?;
+        let albums: HashMap<String, AlbumInfo> = self
+            .album_infos(albums)
+            .await?
+            .into_iter()
+            .map(|album| (album.id.clone(), album))
+            .collect();
+        for track in tracks
+            .iter_mut()
+            .filter(|track| track.status == EntryStatus::Downloadable)
+        {
+            if let Some(album) = albums.get(&track.album.id) {
+                track.album = album.clone();
+            }
+        }
+        Ok(tracks)
+    }
+
+    /// Convert full albums, in order. Spotify rarely fills album genres, so
+    /// albums without any get their main artist's, fetched in batches.
+    async fn album_infos(&self, albums: Vec<Album>) -> Result<Vec<AlbumInfo>> {
+        let mut artist_ids: Vec<String> = Vec::new();
+        for album in albums.iter().filter(|album| album.genres.is_empty()) {
+            if let Some(artist) = album.artists.first()
+                && !artist_ids.contains(&artist.id)
+            {
+                artist_ids.push(artist.id.clone());
+            }
+        }
+        let artists: Vec<Artist> = get_several(
+            &self.session,
+            "artists",
+            &artist_ids,
+            ARTISTS_PER_REQUEST,
+            None,
+        )
+        .await?;
+        let genres: HashMap<String, Vec<String>> = artists
+            .into_iter()
+            .map(|artist| (artist.id, artist.genres))
+            .collect();
+
+        Ok(albums
+            .iter()
+            .map(|album| {
+                let mut info = album_info(album);
+                if info.genres.is_empty()
+                    && let Some(artist_genres) = album
+                        .artists
+                        .first()
+                        .and_then(|artist| genres.get(&artist.id))
+                {
+                    info.genres = artist_genres.clone();
+                }
+                info
+            })
+            .collect())
+    }
+
+    /// Session and market for podcast and playlist lookups: the configured
+    /// market, else the user's own market when logged in, else
+    /// [`PODCAST_MARKET`].
+    fn podcast_session(&self) -> (&SpotifySession, &str) {
+        let (session, market) = match self.user_session.as_deref() {
+            Some(session) => (session, "from_token"),
+            None => (&*self.session, PODCAST_MARKET),
+        };
+        (session, self.market.as_deref().unwrap_or(market))
+    }
+
+    fn require_user_session(&self) -> Result<&SpotifySession> {
+        self.user_session.as_deref().ok_or_else(|| {
+            Error::SpotifyAuth(
+                "not logged in to a Spotify account, run `rustifydl login` first".to_string(),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl MetadataSource for SpotifySource {
+    async fn track(&self, id: &str) -> Result<TrackInfo> {
+        let track: Track = self
+            .session
+            .get(&self.with_market(&format!("tracks/{id}")))
+            .await?;
+        let mut tracks = self.with_full_albums(vec![track_info(track)]).await?;
+        Ok(tracks.remove(0))
+    }
+
+    async fn tracks(&self, ids: &[String]) -> Result<Vec<TrackInfo>> {
+        let tracks: Vec<Track> = get_several(
+            &self.session,
+            "tracks",
+            ids,
+            TRACKS_PER_REQUEST,
+            self.market.as_deref(),
+        )
+        .await?;
+        self.with_full_albums(tracks.into_iter().map(track_info).collect())
+            .await
+    }
+
+    async fn album_tracks(&self, id: &str) -> Result<Vec<TrackInfo>> {
+        let album: Album = self
+            .session
+            .get(&self.with_market(&format!("albums/{id}")))
+            .await?;
+        let ids: Vec<String> = self
+            .session
+            .get_all(album.tracks.clone())
+            .await?
+            .into_iter()
+            // Relinked songs keep the requested ID, so they're looked up as such.
+            .map(|song| {
+                song.linked_from
+                    .and_then(|original| original.id)
+                    .unwrap_or(song.id)
+            })
+            .collect();
+        // The album's track list lacks ISRCs, so the full tracks are fetched too.
+        let tracks: Vec<Track> = get_several(
+            &self.session,
+            "tracks",
+            &ids,
+            TRACKS_PER_REQUEST,
+            self.market.as_deref(),
+        )
+        .await?;
+        let name = album.name.clone();
+        let info = self.album_infos(vec![album]).await?.remove(0);
+
+        let songs: Vec<TrackInfo> = tracks
+            .into_iter()
+            .map(|track| TrackInfo {
+                album: info.clone(),
+                ..track_info(track)
+            })
+            .collect();
+        info!("Found {} tracks in {name}!", songs.len());
+        Ok(songs)
+    }
+
+    async fn playlist_tracks(&self, id: &str) -> Result<Vec<TrackInfo>> {
+        // Private and collaborative playlists are only visible to the user,
+        // and episodes come back as null unless a market is named.
+        let (session, market) = self.podcast_session();
+        let playlist: PlaylistObject = session
+            .get(&format!(
+                "playlists/{id}?additional_types=track,episode&market={market}"
+            ))
+            .await?;
+        let entries = session.get_all(playlist.tracks).await?;
+
+        // Every entry is kept, so the report can name the ones that can't be
+        // downloaded.
+        let mut songs = Vec::new();
+        for (position, entry) in entries.into_iter().enumerate() {
+            let song = match entry.track {
+                Some(PlaylistItem::Track(track)) => track_info(*track),
+                Some(PlaylistItem::Episode(episode)) => match episode.show.clone() {
+                    Some(show) => episode_info(*episode, &show),
+                    None => removed_entry(position, Some(episode.name)),
+                },
+                Some(PlaylistItem::Local(local)) => local_track_info(local),
+                None => removed_entry(position, None),
+            };
+            songs.push(song);
+        }
+        let downloadable = songs
+            .iter()
+            .filter(|song| song.status.is_downloadable())
+            .count();
+        info!(
+            "Found {downloadable} tracks in {}{}!",
+            playlist.name,
+            match songs.len() - downloadable {
+                0 => String::new(),
+                missing => format!(", {missing} more can't be downloaded"),
+            }
+        );
+        self.with_full_albums(songs).await
+    }
+
+    async fn album(&self, id: &str) -> Result<AlbumInfo> {
+        let album: Album = self
+            .session
+            .get(&self.with_market(&format!("albums/{id}")))
+            .await?;
+        Ok(self.album_infos(vec![album]).await?.remove(0))
+    }
+
+    async fn artist_albums(&self, id: &str, types: &[ReleaseType]) -> Result<Vec<AlbumInfo>> {
+        let mut albums = Vec::new();
+        // One request per type, so the result is grouped in the caller's order.
+        for release_type in types {
+            let page: Page<SimplifiedAlbum> = self
+                .session
+                .get(&self.with_market(&format!(
+                    "artists/{id}/albums?include_groups={release_type}&limit=50"
+                )))
+                .await?;
+            let releases = self.session.get_all(page).await?;
+            info!("Found {} {release_type} releases", releases.len());
+            albums.extend(releases.into_iter().map(simplified_album_info));
+        }
+        Ok(albums)
+    }
+
+    async fn episode(&self, id: &str) -> Result<TrackInfo> {
+        let (session, market) = self.podcast_session();
+        let episode: EpisodeObject = session
+            .get(&format!("episodes/{id}?market={market}"))
+            .await?;
+        let show = episode.show.clone().ok_or_else(|| Error::Spotify {
+            status: None,
+            message: format!("Episode {id} has no show"),
+        })?;
+        Ok(episode_info(episode, &show))
+    }
+
+    async fn show_episodes(&self, id: &str) -> Result<Vec<TrackInfo>> {
+        let (session, market) = self.podcast_session();
+        let show: ShowObject = session.get(&format!("shows/{id}?market={market}")).await?;
+        let episodes = session.get_all(show.episodes).await?;
+        info!(
+            "Found {} episodes of {}!",
+            episodes.len(),
+            show.summary.name
+        );
+        Ok(episodes
+            .into_iter()
+            .map(|episode| episode_info(episode, &show.summary))
+            .collect())
+    }
+
+    async fn search_tracks(&self, query: &str, limit: usize) -> Result<Vec<TrackInfo>> {
+        #[derive(Deserialize)]
+        struct SearchResults {
+            tracks: Page<Track>,
+        }
+
+        let results: SearchResults = self
+            .session
+            .get(&self.with_market(&format!(
+                "search?type=track&limit={}&q={}",
+                limit.clamp(1, 50),
+                percent_encode(query)
+            )))
+            .await?;
+        Ok(results
+            .tracks
+            .items
+            .into_iter()
+            .flatten()
+            .map(track_info)
+            .collect())
+    }
+
+    async fn liked_tracks(&self) -> Result<Vec<TrackInfo>> {
+        let session = self.require_user_session()?;
+        let page: Page<SavedTrack> = session.get(&self.with_market("me/tracks?limit=50")).await?;
+        let saved = session.get_all(page).await?;
+        info!("Found {} Liked Songs!", saved.len());
+        let tracks = saved.into_iter().map(|saved| track_info(saved.track));
+        self.with_full_albums(tracks.collect()).await
+    }
+
+    async fn saved_albums(&self) -> Result<Vec<AlbumInfo>> {
+        let session = self.require_user_session()?;
+        let page: Page<SavedAlbum> = session.get(&self.with_market("me/albums?limit=50")).await?;
+        let saved = session.get_all(page).await?;
+        info!("Found {} saved albums!", saved.len());
+        Ok(saved.iter().map(|saved| album_info(&saved.album)).collect())
+    }
+
+    async fn user_playlists(&self) -> Result<Vec<PlaylistInfo>> {
+        let session = self.require_user_session()?;
+        let page: Page<SimplifiedPlaylist> = session.get("me/playlists?limit=50").await?;
+        let playlists = session.get_all(page).await?;
+        info!("Found {} playlists in your library!", playlists.len());
+        Ok(playlists
+            .into_iter()
+            .map(|playlist| PlaylistInfo {
+                owner: playlist.owner.display_name.unwrap_or(playlist.owner.id),
+                total_tracks: playlist.tracks.map_or(0, |tracks| tracks.total),
+                id: playlist.id,
+                name: playlist.name,
+            })
+            .collect())
+    }
+}
+
+/// Look up `ids` through a "several items" endpoint such as `tracks?ids=...`,
+/// `chunk_size` at a time. The result keeps the order of `ids`; IDs Spotify
+/// doesn't know are left out.
+async fn get_several<T: DeserializeOwned>(
+    session: &SpotifySession,
+    endpoint: &str,
+    ids: &[String],
+    chunk_size: usize,
+    market: Option<&str>,
+) -> Result<Vec<T>> {
+    let market = market.map_or(String::new(), |market| format!("&market={market}"));
+    let mut items = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(chunk_size) {
+        let mut response: HashMap<String, Vec<Option<T>>> = session
+            .get(&format!("{endpoint}?ids={}{market}", chunk.join(",")))
+            .await?;
+        items.extend(
+            response
+                .remove(endpoint)
+                .unwrap_or_default()
+                .into_iter()
+                .flatten(),
+        );
+    }
+    Ok(items)
+}
+
+/// Convert a full Spotify track into the crate model.
+///
+/// A track relinked for the market keeps the ID that was asked for, so tags
+/// and the report don't change with the market. Tracks Spotify reports as
+/// not playable, or as sold in no market at all, are marked
+/// [`Unavailable`](EntryStatus::Unavailable).
+fn track_info(track: Track) -> TrackInfo {
+    let status = if track.is_playable == Some(false) {
+        let reason = match track.restrictions.map(|restriction| restriction.reason) {
+            Some(RestrictionReason::Product) => "unavailable in market (product restriction)",
+            Some(RestrictionReason::Explicit) => "unavailable in market (explicit content)",
+            _ => "unavailable in market",
+        };
+        EntryStatus::Unavailable(reason.to_string())
+    } else if track.available_markets.as_ref().is_some_and(Vec::is_empty) {
+        EntryStatus::Unavailable("not available in any market".to_string())
+    } else {
+        EntryStatus::Downloadable
+    };
+    let (artist_ids, artists) = track
+        .artists
+        .into_iter()
+        .map(|artist| (artist.id, artist.name))
+        .unzip();
+    TrackInfo {
+        artists,
+        artist_ids,
+        album: simplified_album_info(track.album),
+        id: track
+            .linked_from
+            .and_then(|original| original.id)
+            .unwrap_or(track.id),
+        name: track.name,
+        disc_number: track.disc_number,
+        track_number: track.track_number,
+        duration_ms: track.duration_ms,
+        isrc: track.external_ids.isrc,
+        kind: TrackKind::Song,
+        description: None,
+        status,
+    }
+}
+
+/// A local file in a playlist. Its `spotify:local:` URI stands in for the ID.
+fn local_track_info(local: LocalTrack) -> TrackInfo {
+    let name = |object: LocalName| object.name.filter(|name| !name.is_empty());
+    TrackInfo {
+        id: local.uri,
+        name: local.name,
+        artists: local.artists.into_iter().filter_map(name).collect(),
+        album: AlbumInfo {
+            name: local.album.and_then(name).unwrap_or_default(),
+            ..AlbumInfo::default()
+        },
+        duration_ms: local.duration_ms,
+        status: EntryStatus::LocalFile,
+        ..TrackInfo::default()
+    }
+}
+
+/// A playlist entry whose track or episode is gone from the catalog. It has
+/// no ID, so it's named after its position.
+fn removed_entry(position: usize, name: Option<String>) -> TrackInfo {
+    TrackInfo {
+        name: name.unwrap_or_else(|| format!("Removed entry {}", position + 1)),
+        status: EntryStatus::Removed,
+        ..TrackInfo::default()
+    }
+}
+
+/// Convert a podcast episode into the crate model: the show stands in for
+/// the album and the publisher for the artist.
+fn episode_info(episode: EpisodeObject, show: &ShowSummary) -> TrackInfo {
+    let cover_url = episode
+        .images
+        .first()
+        .or(show.images.first())
+        .map(|image| image.url.clone());
+    TrackInfo {
+        id: episode.id,
+        name: episode.name,
+        artists: vec![show.publisher.clone()],
+        artist_ids: Vec::new(),
+        album: AlbumInfo {
+            id: show.id.clone(),
+            name: show.name.clone(),
+            artists: vec![show.publisher.clone()],
+            genres: vec!["Podcast".to_string()],
+            label: None,
+            release_date: episode.release_date,
+            total_tracks: show.total_episodes,
+            cover_url,
+        },
+        disc_number: 0,
+        track_number: 0,
+        duration_ms: episode.duration_ms,
+        isrc: None,
+        kind: TrackKind::Episode,
+        description: Some(episode.description).filter(|description| !description.is_empty()),
+        status: EntryStatus::Episode,
+    }
+}
+
+/// The album fields Spotify embeds in a track object.
+fn simplified_album_info(album: SimplifiedAlbum) -> AlbumInfo {
+    AlbumInfo {
+        cover_url: album.images.into_iter().next().map(|image| image.url),
+        id: album.id,
+        name: album.name,
+        artists: album
+            .artists
+            .into_iter()
+            .map(|artist| artist.name)
+            .collect(),
+        genres: Vec::new(),
+        label: None,
+        release_date: album.release_date,
+        total_tracks: album.total_tracks,
+    }
+}
+
+fn album_info(album: &Album) -> AlbumInfo {
+    AlbumInfo {
+        id: album.id.clone(),
+        name: album.name.clone(),
+        artists: album
+            .artists
+            .iter()
+            .map(|artist| artist.name.clone())
+            .collect(),
+        genres: album.genres.clone(),
+        label: Some(album.label.clone()).filter(|label| !label.is_empty()),
+        release_date: album.release_date.clone(),
+        total_tracks: album.total_tracks,
+        cover_url: album.images.first().map(|image| image.url.clone()),
+    }
+}
 
 /// Fetch a single track by Spotify ID.
 ///
-/// Returns a map with one entry mapping a display name to its `spotify_rs::model::track::Track`.
-pub async fn fetch_track(
+/// Returns a plan with that one track.
+pub async fn fetch_track(id: &str, source: &dyn MetadataSource) -> Result<Vec<PlannedTrack>> {
+    let track = source.track(id).await?;
+    Ok(plan_tracks(vec![track], false))
+}
+
+/// Fetch all tracks from a playlist by ID, in playlist order.
+///
+/// If `DownloadOptions::no_dupes` is true, tracks with the same display name
+/// as an earlier entry are dropped; otherwise they are kept under a
+/// disambiguated name. See [`plan_tracks`].
+pub async fn fetch_playlist(
     id: &str,
+    source: &dyn MetadataSource,
     options: &DownloadOptions,
-) -> Result<HashMap<String, Track>, Box<dyn std::error::Error + Send + Sync>> {
-    let spotify =
-        ClientCredsClient::authenticate(&options.client_id, &options.client_secret).await?;
-    let track = spotify_rs::track(id).get(&spotify).await?;
-    let mut songs = HashMap::<String, Track>::new();
-    songs.insert(
-        format!(
-            "{} - {}",
-            track
-                .artists
-                .iter()
-                .map(|artist| artist.name.as_str())
-                .collect::<Vec<_>>()
-                .join(", "),
-            track.name,
-        ),
-        track,
-    );
-    Ok(songs)
-}
-
-/// Fetch all tracks from a playlist by ID.
+) -> Result<Vec<PlannedTrack>> {
+    Ok(plan_tracks(
+        source.playlist_tracks(id).await?,
+        options.no_dupes,
+    ))
+}
+
+/// Fetch all tracks from a Album by ID, in album order.
 ///
-/// The result map keys are display names. If `DownloadOptions::no_dupes` is
-/// false and a duplicate title is encountered, the album name is appended to
-/// disambiguate entries.
-/// Returns a HashMap with the track name as the key and the `` object.
-pub async fn fetch_playlist(
+/// Tracks sharing a title (e.g. two "Intro"s) all stay in the plan under
+/// distinct names. They are marked [`whole_album`](PlannedTrack::whole_album),
+/// so their audio comes from one release.
+pub async fn fetch_album(id: &str, source: &dyn MetadataSource) -> Result<Vec<PlannedTrack>> {
+    Ok(plan_tracks(source.album_tracks(id).await?, false)
+        .into_iter()
+        .map(|planned| PlannedTrack {
+            whole_album: true,
+            ..planned
+        })
+        .collect())
+}
+
+/// Fetch an artist's discography by ID.
+///
+/// Only releases of the types in `DownloadOptions::release_types` are
+/// included. Each album goes into its own folder, and recordings already on
+/// an earlier release are skipped. See [`plan_albums`]. Of releases the
+/// artist only appears on, just the tracks crediting the artist are kept.
+pub async fn fetch_artist(
     id: &str,
+    source: &dyn MetadataSource,
     options: &DownloadOptions,
-) -> Result<HashMap<String, Track>, Box<dyn std::error::Error + Send + Sync>> {
-    let spotify =
-        ClientCredsClient::authenticate(&options.client_id, &options.client_secret).await?;
-    let mut songs = HashMap::<String, Track>::new();
-
-    let playlist = spotify_rs::playlist(id).get(&spotify).await?;
-    let tracks = playlist.tracks.get_all(&spotify).await?;
-    for song in tracks {
-        if let Some(song) = song {
-            match song.track {
-                PlayableItem::Track(track) => {
-                    if songs.contains_key(
-                        format!(
-                            "{} - {}",
-                            track
-                                .artists
-                                .iter()
-                                .map(|artist| artist.name.as_str())
-                                .collect::<Vec<_>>()
-                                .join(", "),
-                            track.name,
-                        )
-                        .as_str(),
-                    ) && !options.no_dupes
-                    {
-                        songs.insert(
-                            format!(
-                                "{} - {} - {}",
-                                track
-                                    .artists
-                                    .iter()
-                                    .map(|artist| artist.name.as_str())
-                                    .collect::<Vec<_>>()
-                                    .join(", "),
-                                track.album.name,
-                                track.name,
-                            ),
-                            track,
-                        );
-                    } else {
-                        songs.insert(
-                            format!(
-                                "{} - {}",
-                                track
-                                    .artists
-                                    .iter()
-                                    .map(|artist| artist.name.as_str())
-                                    .collect::<Vec<_>>()
-                                    .join(", "),
-                                track.name,
-                            ),
-                            track,
-                        );
-                    }
+) -> Result<Vec<PlannedTrack>> {
+    let mut albums = Vec::new();
+    for &release_type in &options.release_types {
+        for album in source.artist_albums(id, &[release_type]).await? {
+            let mut tracks = source.album_tracks(&album.id).await?;
+            // Other artists' releases only contribute the songs the artist is on.
+            if release_type == ReleaseType::AppearsOn {
+                tracks.retain(|track| {
+                    track.artist_ids.is_empty() || track.artist_ids.iter().any(|a| a == id)
+                });
+                if tracks.is_empty() {
+                    continue;
                 }
-                PlayableItem::Episode(_episode) => {}
             }
-        } else {
-            info!("No song found.");
+            albums.push((album, tracks));
         }
     }
-    info!("Found {} tracks in {}!", songs.len(), playlist.name);
-    Ok(songs)
+    Ok(plan_albums(albums))
 }
 
-/// Fetch all tracks from a Album by ID.
+/// Search the catalog for tracks matching free text such as `"Artist - Title"`.
 ///
-/// The result map keys are display names. If `DownloadOptions::no_dupes` is
-/// false and a duplicate title is encountered, the album name is appended to
-/// disambiguate entries.
-/// Returns a HashMap with the track name as the key and the `spotify_rs::model::track::Track` object.
-pub async fn fetch_album(
-    id: &str,
+/// Returns up to `limit` hits, best match first; pick one and download it
+/// through its URI (see [`SpotifyResource`](crate::SpotifyResource)) to get
+/// the usual fully tagged result.
+pub async fn resolve_query(
+    query: &str,
+    source: &dyn MetadataSource,
+    limit: usize,
+) -> Result<Vec<TrackInfo>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(Error::InvalidOptions("search query is empty".to_string()));
+    }
+    let hits = source.search_tracks(query, limit).await?;
+    info!("Found {} tracks for \"{query}\"", hits.len());
+    Ok(hits)
+}
+
+/// Fetch a single podcast episode by Spotify ID, planned into its show's folder.
+pub async fn fetch_episode(id: &str, source: &dyn MetadataSource) -> Result<Vec<PlannedTrack>> {
+    let episode = source.episode(id).await?;
+    Ok(plan_tracks(vec![episode], false))
+}
+
+/// Fetch every episode of a podcast show by Spotify ID, newest first, into
+/// one folder named after the show.
+pub async fn fetch_show(id: &str, source: &dyn MetadataSource) -> Result<Vec<PlannedTrack>> {
+    Ok(plan_tracks(source.show_episodes(id).await?, false))
+}
+
+/// Fetch the logged-in user's Liked Songs, most recently added first.
+///
+/// Duplicates are handled as for playlists, see [`fetch_playlist`].
+pub async fn fetch_liked(
+    source: &dyn MetadataSource,
     options: &DownloadOptions,
-) -> Result<HashMap<String, Track>, Box<dyn std::error::Error + Send + Sync>> {
-    let spotify =
-        ClientCredsClient::authenticate(&options.client_id, &options.client_secret).await?;
-    let mut songs = HashMap::<String, Track>::new();
-
-    let album = spotify_rs::album(id).get(&spotify).await?;
-    let tracks = album.tracks.get_all(&spotify).await?;
-
-    for song in tracks {
-        if let Some(song) = song {
-            let track = spotify_rs::track(song.id).get(&spotify).await?;
-            songs.insert(
-                format!(
-                    "{} - {}",
-                    track
-                        .artists
-                        .iter()
-                        .map(|artist| artist.name.as_str())
-                        .collect::<Vec<_>>()
-                        .join(", "),
-                    track.name,
-                ),
-                track,
-            );
-        } else {
-            info!("No song found.");
-        }
+) -> Result<Vec<PlannedTrack>> {
+    Ok(plan_tracks(source.liked_tracks().await?, options.no_dupes))
+}
+
+/// Fetch every album in the logged-in user's library, one folder per album.
+/// See [`plan_albums`].
+pub async fn fetch_saved_albums(source: &dyn MetadataSource) -> Result<Vec<PlannedTrack>> {
+    let mut albums = Vec::new();
+    for album in source.saved_albums().await? {
+        let tracks = source.album_tracks(&album.id).await?;
+        albums.push((album, tracks));
+    }
+    Ok(plan_albums(albums))
+}
+
+/// Fetch every playlist the logged-in user owns or follows, one folder per
+/// playlist. See [`plan_playlists`].
+pub async fn fetch_user_playlists(
+    source: &dyn MetadataSource,
+    options: &DownloadOptions,
+) -> Result<Vec<PlannedTrack>> {
+    let mut playlists = Vec::new();
+    for playlist in source.user_playlists().await? {
+        let tracks = source.playlist_tracks(&playlist.id).await?;
+        playlists.push((playlist, tracks));
     }
-    info!("Found {} tracks in {}!", songs.len(), album.name);
-    Ok(songs)
+    Ok(plan_playlists(playlists, options.no_dupes))
 }
@@ -0,0 +1,187 @@
+//! Transcoding fetched audio to the output format.
+//!
+//! The pipeline hands every fetched file to a [`Transcoder`] together with an
+//! [`OutputSpec`]. [`FfmpegTranscoder`] is the default; other encoders or test
+//! doubles plug in through [`Downloader::transcoder`](crate::Downloader::transcoder).
+//!
+//! Example
+//! ```
+//! use std::path::Path;
+//! use rustifydl::Bitrate;
+//! use rustifydl::transcode::{Codec, FfmpegTranscoder, OutputSpec, Quality};
+//!
+//! let ffmpeg = FfmpegTranscoder::new()
+//!     .binary("/opt/ffmpeg/bin/ffmpeg")
+//!     .extra_args(["-map_metadata", "-1"]);
+//! let spec = OutputSpec {
+//!     codec: Some(Codec::Opus),
+//!     quality: Some(Quality::Bitrate(Bitrate::K128)),
+//!     sample_rate: Some(48_000),
+//!     channels: Some(2),
+//! };
+//! let args = ffmpeg.args(Path::new("in.webm"), Path::new("out.opus"), &spec);
+//! assert_eq!(
+//!     args,
+//!     [
+//!         "-i", "in.webm", "-c:a", "libopus", "-b:a", "128k", "-ar", "48000", "-ac", "2",
+//!         "-map_metadata", "-1", "-threads", "0", "-y", "out.opus",
+//!     ]
+//! );
+//! ```
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use log::debug;
+use tokio::process::Command;
+
+use crate::{Bitrate, DownloadOptions, Error, Result, youtube::command_error_print};
+
+/// Audio encoder to use. See [`OutputSpec::codec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    Mp3,
+    Flac,
+    Vorbis,
+    Opus,
+    Aac,
+    /// 16-bit little-endian PCM, as used in WAV.
+    Pcm16,
+}
+
+impl Codec {
+    /// Encoder name passed to ffmpeg as `-c:a`.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            Codec::Mp3 => "libmp3lame",
+            Codec::Flac => "flac",
+            Codec::Vorbis => "libvorbis",
+            Codec::Opus => "libopus",
+            Codec::Aac => "aac",
+            Codec::Pcm16 => "pcm_s16le",
+        }
+    }
+}
+
+/// Target quality. See [`OutputSpec::quality`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quality {
+    /// Constant/average bitrate (`-b:a`).
+    Bitrate(Bitrate),
+    /// Encoder-specific VBR quality level (`-q:a`), e.g. 0-9 for mp3.
+    Vbr(u8),
+}
+
+/// How the output should be encoded. The container always follows the output
+/// file extension; `None` fields are left to the encoder's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct OutputSpec {
+    /// Encoder; `None` picks the container's default.
+    pub codec: Option<Codec>,
+    /// Bitrate or VBR quality.
+    pub quality: Option<Quality>,
+    /// Sample rate in Hz.
+    pub sample_rate: Option<u32>,
+    /// Number of channels.
+    pub channels: Option<u8>,
+}
+
+impl OutputSpec {
+    /// The spec used when none is given: the bitrate from `options`,
+    /// everything else at the encoder's defaults.
+    pub fn from_options(options: &DownloadOptions) -> Self {
+        OutputSpec {
+            quality: Some(Quality::Bitrate(options.bitrate)),
+            ..OutputSpec::default()
+        }
+    }
+}
+
+/// Converts a fetched file into the final output file.
+///
+/// Called from several download tasks at once, so implementations must not
+/// block the runtime while encoding.
+#[async_trait]
+pub trait Transcoder: Send + Sync {
+    /// Encode `input` to `output` according to `spec`, overwriting `output`.
+    async fn transcode(&self, input: &Path, output: &Path, spec: &OutputSpec) -> Result<()>;
+}
+
+/// [`Transcoder`] that shells out to ffmpeg.
+#[derive(Debug, Clone)]
+pub struct FfmpegTranscoder {
+    binary: PathBuf,
+    extra_args: Vec<OsString>,
+}
+
+impl Default for FfmpegTranscoder {
+    fn default() -> Self {
+        FfmpegTranscoder {
+            binary: PathBuf::from("ffmpeg"),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl FfmpegTranscoder {
+    /// Use `ffmpeg` from `PATH` with no extra arguments.
+    pub fn new() -> Self {
+        FfmpegTranscoder::default()
+    }
+
+    /// Path to the ffmpeg binary.
+    pub fn binary(mut self, binary: impl Into<PathBuf>) -> Self {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Arguments inserted after the encoding options, before the output file.
+    pub fn extra_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.extra_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The arguments ffmpeg is run with, without the binary itself.
+    ///
+    /// Uses `-threads 0` to allow ffmpeg to use all cores.
+    pub fn args(&self, input: &Path, output: &Path, spec: &OutputSpec) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-i".into(), input.into()];
+        if let Some(codec) = spec.codec {
+            args.extend(["-c:a".into(), codec.ffmpeg_name().into()]);
+        }
+        match spec.quality {
+            Some(Quality::Bitrate(bitrate)) => {
+                args.extend(["-b:a".into(), bitrate.to_string().into()])
+            }
+            Some(Quality::Vbr(level)) => args.extend(["-q:a".into(), level.to_string().into()]),
+            None => {}
+        }
+        if let Some(sample_rate) = spec.sample_rate {
+            args.extend(["-ar".into(), sample_rate.to_string().into()]);
+        }
+        if let Some(channels) = spec.channels {
+            args.extend(["-ac".into(), channels.to_string().into()]);
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args.extend(["-threads".into(), "0".into(), "-y".into(), output.into()]);
+        args
+    }
+}
+
+#[async_trait]
+impl Transcoder for FfmpegTranscoder {
+    /// On failure, the stderr from ffmpeg is surfaced in [`Error::Ffmpeg`].
+    async fn transcode(&self, input: &Path, output: &Path, spec: &OutputSpec) -> Result<()> {
+        let args = self.args(input, output, spec);
+        debug!("Running {} {:?}", self.binary.display(), args);
+        let output = Command::new(&self.binary).args(args).output().await?;
+        command_error_print(output, |status, stderr| Error::Ffmpeg { status, stderr })
+    }
+}
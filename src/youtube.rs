@@ -1,24 +1,39 @@
 //! YouTube search and download helpers.
 //!
 //! Behavior:
-//! - Search YouTube Music for a best-effort match to the provided name.
+//! - Search YouTube Music for the track and pick the best-scoring result,
+//!   see [`matching`](crate::matching).
+//! - For whole albums, find the same release on YouTube Music and take every
+//!   track from it.
 //! - Download an audio-only stream and write a temporary file to `output_dir/temp`.
 //! - Transcode with ffmpeg to the final format and move to `output_dir`.
 //! - Skip work if the final output already exists.
+//!
+//! [`YoutubeSource`] wraps the search and yt-dlp steps as the default
+//! [`AudioSource`].
 
-use crate::DownloadOptions;
+use crate::{
+    DownloadOptions, Error, Result,
+    audio::{AudioCandidate, AudioSource, MatchMethod},
+    matching::{isrc_match, match_album, rank},
+    progress::{NoopObserver, ProgressEvent, ProgressObserver, TrackProgress},
+    source::{AlbumInfo, TrackInfo},
+    transcode::{FfmpegTranscoder, OutputSpec, Transcoder},
+};
 
-use clap::error::Result;
+use async_trait::async_trait;
 use hex;
-use log::info;
-use rustypipe::client::RustyPipe;
+use log::{debug, info, warn};
+use rustypipe::{client::RustyPipe, model::VideoItem};
 use sha2::digest::generic_array::GenericArray;
 use sha2::{Digest, Sha256};
 use std::fs::{File, remove_file};
 use std::io::{BufRead, BufReader, copy};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
 use std::{env, fs};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt};
 use toml::Value;
 
 /// Result of a download attempt.
@@ -28,6 +43,188 @@ pub enum DownloadResult {
     Completed,
     /// File already existed and was skipped.
     Skipped,
+    /// The entry can't be downloaded, see [`EntryStatus`](crate::source::EntryStatus).
+    NotDownloadable,
+}
+
+/// Album search results fetched and checked before giving up on a release.
+const ALBUMS_TO_CHECK: usize = 3;
+
+/// [`AudioSource`] that searches YouTube Music and downloads with yt-dlp.
+pub struct YoutubeSource {
+    ytdlp_path: PathBuf,
+    concurrent_fragments: usize,
+}
+
+impl YoutubeSource {
+    /// Use the yt-dlp binary at `ytdlp_path`, as returned by [`download_ytdlp`].
+    pub fn new(ytdlp_path: PathBuf) -> Self {
+        YoutubeSource {
+            ytdlp_path,
+            concurrent_fragments: 1,
+        }
+    }
+
+    /// Number of fragments yt-dlp downloads in parallel (`-N`). Defaults to 1.
+    pub fn concurrent_fragments(mut self, concurrent_fragments: usize) -> Self {
+        self.concurrent_fragments = concurrent_fragments.max(1);
+        self
+    }
+}
+
+#[async_trait]
+impl AudioSource for YoutubeSource {
+    async fn candidates(&self, track: &TrackInfo) -> Result<Vec<AudioCandidate>> {
+        let candidates = if track.is_episode() {
+            // YouTube Music search only knows songs; podcasts are regular videos.
+            search_video_candidates(&format!("{} {}", track.album.name, track.name)).await?
+        } else {
+            if let Some(candidate) = isrc_candidate(track).await {
+                return Ok(vec![candidate]);
+            }
+            search_candidates(&track.display_name()).await?
+        };
+        Ok(rank(track, candidates)
+            .into_iter()
+            .map(|(candidate, _)| candidate)
+            .collect())
+    }
+
+    /// Searches YouTube Music albums for artist and title, and takes the
+    /// first of the top results that [`match_album`] accepts.
+    async fn album_candidates(
+        &self,
+        album: &AlbumInfo,
+        tracks: &[TrackInfo],
+    ) -> Result<Vec<Option<AudioCandidate>>> {
+        let rp = RustyPipe::new();
+        let query = format!("{} {}", album.artists.join(", "), album.name);
+        let results = rp.query().music_search_albums(&query).await?;
+        for item in results.items.items.into_iter().take(ALBUMS_TO_CHECK) {
+            let release = match rp.query().music_album(&item.id).await {
+                Ok(release) => release,
+                Err(e) => {
+                    warn!("Couldn't load YouTube Music album {}: {e}", item.id);
+                    continue;
+                }
+            };
+            let candidates: Vec<AudioCandidate> = release
+                .tracks
+                .into_iter()
+                .map(|item| AudioCandidate {
+                    id: item.id,
+                    title: item.name,
+                    artists: item.artists.into_iter().map(|artist| artist.name).collect(),
+                    album: Some(release.name.clone()),
+                    duration: item.duration.map(|secs| Duration::from_secs(secs.into())),
+                    method: MatchMethod::Album,
+                })
+                .collect();
+            match match_album(album, tracks, &candidates) {
+                Some(matches) => {
+                    info!(
+                        "Matched {} to the YouTube Music album {} ({}/{} tracks)",
+                        album.name,
+                        release.id,
+                        matches.iter().flatten().count(),
+                        tracks.len()
+                    );
+                    return Ok(matches);
+                }
+                None => debug!(
+                    "YouTube Music album {} ({} tracks) doesn't match {}",
+                    release.id,
+                    candidates.len(),
+                    album.name
+                ),
+            }
+        }
+        info!(
+            "No YouTube Music album matches {}, searching track by track",
+            album.name
+        );
+        Ok(vec![None; tracks.len()])
+    }
+
+    async fn fetch(
+        &self,
+        candidate: &AudioCandidate,
+        dest: &Path,
+        track: &TrackProgress,
+        observer: &dyn ProgressObserver,
+    ) -> Result<PathBuf> {
+        let fixed_path = if self.ytdlp_path.is_relative() && !self.ytdlp_path.starts_with(".") {
+            PathBuf::from(".").join(&self.ytdlp_path)
+        } else {
+            self.ytdlp_path.clone()
+        };
+        let mut child = tokio::process::Command::new(path_str(&fixed_path)?)
+            .args([
+                "--audio-format",
+                "opus",
+                "-N",
+                &self.concurrent_fragments.to_string(),
+                "--format",
+                "bestaudio",
+                "--newline",
+                "--progress-template",
+                PROGRESS_TEMPLATE,
+                "-o",
+                path_str(dest)?,
+                "-x",
+                &candidate.id,
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Drain stderr in its own task so a chatty yt-dlp can't block on a full pipe.
+        let mut stderr_pipe = child.stderr.take();
+        let stderr_reader = tokio::spawn(async move {
+            let mut stderr = Vec::new();
+            if let Some(pipe) = stderr_pipe.as_mut() {
+                let _ = pipe.read_to_end(&mut stderr).await;
+            }
+            stderr
+        });
+        let mut stdout = Vec::new();
+        if let Some(pipe) = child.stdout.take() {
+            let mut lines = tokio::io::BufReader::new(pipe).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match parse_progress(&line) {
+                    Some((downloaded, total)) => observer.on_event(&ProgressEvent::Downloading {
+                        track: track.clone(),
+                        downloaded,
+                        total,
+                    }),
+                    None => stdout.extend_from_slice(line.as_bytes()),
+                }
+            }
+        }
+        let download_video = Output {
+            status: child.wait().await?,
+            stdout,
+            stderr: stderr_reader.await.unwrap_or_default(),
+        };
+        let download_video_status = download_video.status.code();
+
+        command_error_print(download_video, |status, stderr| Error::YtDlp {
+            status,
+            stderr,
+        })?;
+
+        let file = PathBuf::from(format!("{}.opus", path_str(dest)?));
+        if !file.exists() {
+            return Err(Error::YtDlp {
+                status: download_video_status,
+                stderr: format!(
+                    "Download for {} failed or didn't start: File not Found",
+                    track.name
+                ),
+            });
+        }
+        Ok(file)
+    }
 }
 
 /// Search YouTube Music for `name` and calls the `rustifydl::youtube::download` fuction to download the video.
@@ -35,15 +232,21 @@ pub async fn search_yt(
     name: &str,
     options: &DownloadOptions,
     ytdlp_path: PathBuf,
-) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
-    let rp = RustyPipe::new();
-    let search_results = rp.query().music_search_tracks(name).await?;
+) -> Result<DownloadResult> {
+    let id = find_video(name).await?;
+    let track = TrackProgress {
+        index: 0,
+        total: 1,
+        name: name.to_string(),
+    };
 
     if let DownloadResult::Skipped = download(
-        search_results.items.items[0].id.as_str(),
+        id.as_str(),
         name,
         options,
         ytdlp_path,
+        &track,
+        &NoopObserver,
     )
     .await?
     {
@@ -52,119 +255,176 @@ pub async fn search_yt(
     Ok(DownloadResult::Completed)
 }
 
+/// Search YouTube Music for `name` and return the ID of the best-scoring
+/// track result. A name of the form `"Artists - Title"` is scored by artist
+/// and title; with no duration to compare, [`YoutubeSource`] matches catalog
+/// tracks more reliably.
+///
+/// Returns [`Error::NoResults`] when the search comes back empty.
+pub async fn find_video(name: &str) -> Result<String> {
+    let track = match name.split_once(" - ") {
+        Some((artists, title)) => TrackInfo {
+            name: title.to_string(),
+            artists: artists.split(", ").map(str::to_string).collect(),
+            ..TrackInfo::default()
+        },
+        None => TrackInfo {
+            name: name.to_string(),
+            ..TrackInfo::default()
+        },
+    };
+    rank(&track, search_candidates(name).await?)
+        .into_iter()
+        .next()
+        .map(|(candidate, _)| candidate.id)
+        .ok_or_else(|| Error::NoResults(name.to_string()))
+}
+
+/// Search YouTube Music for `query` and return every track result, in result order.
+async fn search_candidates(query: &str) -> Result<Vec<AudioCandidate>> {
+    let rp = RustyPipe::new();
+    let search_results = rp.query().music_search_tracks(query).await?;
+    Ok(search_results
+        .items
+        .items
+        .into_iter()
+        .map(|item| AudioCandidate {
+            id: item.id,
+            title: item.name,
+            artists: item.artists.into_iter().map(|artist| artist.name).collect(),
+            album: item.album.map(|album| album.name),
+            duration: item.duration.map(|secs| Duration::from_secs(secs.into())),
+            method: MatchMethod::Text,
+        })
+        .collect())
+}
+
+/// Search YouTube Music by the track's ISRC and return the top result if
+/// [`isrc_match`] accepts it. `None` if the track has no ISRC, the search
+/// fails or the result isn't the track, so the caller falls back to a text
+/// search.
+async fn isrc_candidate(track: &TrackInfo) -> Option<AudioCandidate> {
+    let isrc = track.isrc.as_deref()?;
+    match search_candidates(isrc).await {
+        Ok(candidates) => {
+            let candidate = isrc_match(track, candidates);
+            if candidate.is_none() {
+                debug!(
+                    "No ISRC match for {}, searching by name",
+                    track.display_name()
+                );
+            }
+            candidate.map(|candidate| AudioCandidate {
+                method: MatchMethod::Isrc,
+                ..candidate
+            })
+        }
+        Err(e) => {
+            debug!("ISRC search for {} failed: {e}", track.display_name());
+            None
+        }
+    }
+}
+
+/// Regular YouTube search, without live streams and shorts.
+async fn search_video_candidates(query: &str) -> Result<Vec<AudioCandidate>> {
+    let rp = RustyPipe::new();
+    let search_results = rp.query().search::<VideoItem, _>(query).await?;
+    Ok(search_results
+        .items
+        .items
+        .into_iter()
+        .filter(|item| !item.is_live && !item.is_short)
+        .map(|item| AudioCandidate {
+            id: item.id,
+            title: item.name,
+            artists: item
+                .channel
+                .into_iter()
+                .map(|channel| channel.name)
+                .collect(),
+            album: None,
+            duration: item.duration.map(|secs| Duration::from_secs(secs.into())),
+            method: MatchMethod::Text,
+        })
+        .collect())
+}
+
 /// Download by YouTube video id and transcode to the target format using ffmpeg.
 ///
-/// The temporary file is saved under `output_dir/temp/`
+/// The temporary file is saved under `output_dir/temp/`. Byte progress reported
+/// by yt-dlp and the start of the transcode are sent to `observer` for `track`.
 pub async fn download(
     id: &str,
     name: &str,
     options: &DownloadOptions,
     ytdlp_path: PathBuf,
-) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+    track: &TrackProgress,
+    observer: &dyn ProgressObserver,
+) -> Result<DownloadResult> {
     fs::create_dir_all(options.output_dir.clone())?;
-    let mut file = PathBuf::from(format!("{}/temp/{}", options.output_dir, name));
-
     let processed_file = PathBuf::from(format!(
         "{}/{}.{}",
-        options.output_dir,
-        name,
-        options.format.clone()
+        options.output_dir, name, options.format
     ));
     if processed_file.exists() {
         info!("File already exists, skipping: {name}");
         return Ok(DownloadResult::Skipped);
     }
 
-    let fixed_path = if ytdlp_path.is_relative() && !ytdlp_path.starts_with(".") {
-        PathBuf::from(".").join(ytdlp_path)
-    } else {
-        ytdlp_path
+    let candidate = AudioCandidate {
+        id: id.to_string(),
+        title: name.to_string(),
+        ..AudioCandidate::default()
     };
-    let download_video = Command::new(fixed_path.to_str().ok_or("Invalid UTF-8 in file path")?)
-        .args([
-            "--audio-format",
-            "opus",
-            "-N",
-            &options.concurrent_downloads.clone().to_string(),
-            "--format",
-            "bestaudio",
-            "-o",
-            file.to_str().ok_or("Invalid UTF-8 in file path")?,
-            "-x",
-            id,
-        ])
-        .output()?;
-
-    command_error_print(download_video)?;
+    let dest = PathBuf::from(format!("{}/temp/{}", options.output_dir, name));
+    let file = YoutubeSource::new(ytdlp_path)
+        .concurrent_fragments(options.concurrent_downloads)
+        .fetch(&candidate, &dest, track, observer)
+        .await?;
 
-    file = PathBuf::from(format!(
-        "{}.opus",
-        file.to_str().ok_or("Invalid UTF-8 in file path")?
-    ));
-    if file.exists() {
-        transcode(
-            file.to_str().ok_or("Invalid UTF-8 in file path")?,
-            processed_file
-                .to_str()
-                .ok_or("Invalid UTF-8 in file path")?,
-            name,
-            options,
-        )?;
-    } else {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidFilename,
-            format!("Download for {name} failed or didn't start: File not Found"),
-        )));
-    }
+    observer.on_event(&ProgressEvent::Transcoding {
+        track: track.clone(),
+    });
+    FfmpegTranscoder::new()
+        .transcode(&file, &processed_file, &OutputSpec::from_options(options))
+        .await?;
+    info!("Completed: {name}");
 
     Ok(DownloadResult::Completed)
 }
 
-/// Transcode the intermediate download to the desired output format using ffmpeg.
-///
-/// Uses `-b:a <bitrate>` and `-threads 0` to allow ffmpeg to use all cores. On
-/// failure, the stderr from ffmpeg is surfaced in the error.
-fn transcode(
-    input_file: &str,
-    output_file: &str,
-    name: &str,
-    options: &DownloadOptions,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let output = Command::new("ffmpeg")
-        .args([
-            "-i",
-            input_file,
-            "-b:a",
-            &options.bitrate,
-            "-threads",
-            "0",
-            "-y",
-            output_file,
-        ])
-        .output()?;
+/// yt-dlp progress line format, parsed back by [`parse_progress`].
+const PROGRESS_TEMPLATE: &str = "download:rustifydl-progress %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s";
 
-    command_error_print(output)?;
-
-    info!("Completed: {name}");
-    Ok(())
+/// Parse a [`PROGRESS_TEMPLATE`] line into `(downloaded, total)` bytes.
+///
+/// yt-dlp prints `NA` for unknown values; the exact total is preferred over the estimate.
+fn parse_progress(line: &str) -> Option<(u64, Option<u64>)> {
+    let mut fields = line.strip_prefix("rustifydl-progress ")?.split_whitespace();
+    let downloaded = fields.next()?.parse::<f64>().ok()? as u64;
+    let total = fields
+        .find_map(|field| field.parse::<f64>().ok())
+        .map(|total| total as u64);
+    Some((downloaded, total))
 }
 
 /// Downloads the latest ytdlpd binary for the users OS
 /// and gives the current user executing permissions (Linux & MacOS)
 /// Also handles custom download directories through the config file if needed
-pub fn download_ytdlp(
-    ytdlp_dir: String,
-) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+pub fn download_ytdlp(ytdlp_dir: String) -> Result<PathBuf> {
     let mut ytdlp_path;
 
-    let config_dir = dirs::config_dir().ok_or("Could not find a valid config directory.")?;
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| Error::Config("Could not find a valid config directory.".to_string()))?;
     let app_config_dir = config_dir.join("RustifyDL");
 
     let config_path = app_config_dir.join("config.toml");
 
     if config_path.exists() && config_path.is_file() && fs::metadata(&config_path)?.len() != 0 {
         let content = fs::read_to_string(&config_path)?;
-        let mut config_file = toml::from_str::<Value>(&content)?;
+        let mut config_file =
+            toml::from_str::<Value>(&content).map_err(|e| Error::Config(e.to_string()))?;
         ytdlp_path = match config_file["ytdlp_dir"].clone().as_str() {
             Some(a) => {
                 if !ytdlp_dir.is_empty() {
@@ -204,10 +464,10 @@ pub fn download_ytdlp(
                     "-L",
                     "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp.exe",
                     "-o",
-                    ytdlp_path.to_str().ok_or("Invalid UTF-8 in file path")?,
+                    path_str(&ytdlp_path)?,
                 ])
                 .output()?;
-            command_error_print(curl)?;
+            command_error_print(curl, setup_error)?;
         } else {
             update_ytdlp(ytdlp_path.clone())?;
         }
@@ -222,34 +482,28 @@ pub fn download_ytdlp(
                         "-L",
                         "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_linux",
                         "-o",
-                        ytdlp_path.to_str().ok_or("Invalid UTF-8 in file path")?,
+                        path_str(&ytdlp_path)?,
                     ]).output()?;
-                    command_error_print(curl)?;
+                    command_error_print(curl, setup_error)?;
 
                     let chmod = Command::new("chmod")
-                        .args([
-                            "a+rx",
-                            ytdlp_path.to_str().ok_or("Invalid UTF-8 in file path")?,
-                        ])
+                        .args(["a+rx", path_str(&ytdlp_path)?])
                         .output()?;
-                    command_error_print(chmod)?;
+                    command_error_print(chmod, setup_error)?;
                 }
                 "macos" => {
                     let curl = Command::new("curl").args([
                         "-L",
                         "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_macos",
                         "-o",
-                        ytdlp_path.to_str().ok_or("Invalid UTF-8 in file path")?,
+                        path_str(&ytdlp_path)?,
                     ]).output()?;
 
-                    command_error_print(curl)?;
+                    command_error_print(curl, setup_error)?;
                     let chmod = Command::new("chmod")
-                        .args([
-                            "a+rx",
-                            ytdlp_path.to_str().ok_or("Invalid UTF-8 in file path")?,
-                        ])
+                        .args(["a+rx", path_str(&ytdlp_path)?])
                         .output()?;
-                    command_error_print(chmod)?;
+                    command_error_print(chmod, setup_error)?;
                 }
                 _ => {}
             }
@@ -262,9 +516,7 @@ pub fn download_ytdlp(
 
 /// Compares latest ytdlp checksum to the installed binary's checksum
 /// to update or repair the binary
-pub fn update_ytdlp(
-    mut ytdlp_path: PathBuf,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub fn update_ytdlp(mut ytdlp_path: PathBuf) -> Result<()> {
     let curl = Command::new("curl")
         .args([
             "-L",
@@ -273,7 +525,7 @@ pub fn update_ytdlp(
             "./checksums",
         ])
         .output()?;
-    command_error_print(curl)?;
+    command_error_print(curl, setup_error)?;
     let checksums_file = File::open(Path::new("./checksums"))?;
     let reader = BufReader::new(checksums_file.try_clone()?);
     let mut checksum = String::new();
@@ -282,7 +534,7 @@ pub fn update_ytdlp(
             checksum = String::from(
                 line.split_whitespace()
                     .next()
-                    .ok_or("Couldn't read checksum")?,
+                    .ok_or_else(|| Error::YtDlpSetup("Couldn't read checksum".to_string()))?,
             );
         }
     } else if env::consts::OS == "macos" {
@@ -290,7 +542,7 @@ pub fn update_ytdlp(
             checksum = String::from(
                 line.split_whitespace()
                     .next()
-                    .ok_or("Couldn't read checksum")?,
+                    .ok_or_else(|| Error::YtDlpSetup("Couldn't read checksum".to_string()))?,
             );
         }
     } else if env::consts::OS == "windows"
@@ -299,7 +551,7 @@ pub fn update_ytdlp(
         checksum = String::from(
             line.split_whitespace()
                 .next()
-                .ok_or("Couldn't read checksum")?,
+                .ok_or_else(|| Error::YtDlpSetup("Couldn't read checksum".to_string()))?,
         );
     }
     let mut ytdlp_file = File::open(ytdlp_path.clone())?;
@@ -308,23 +560,44 @@ pub fn update_ytdlp(
     copy(&mut ytdlp_file, &mut sha256)?;
     let hash = sha256.finalize();
 
-    let checksum_decoded = hex::decode(&checksum)?;
+    let checksum_decoded =
+        hex::decode(&checksum).map_err(|e| Error::YtDlpSetup(format!("Invalid checksum: {e}")))?;
     remove_file("./checksums")?;
     if hash != GenericArray::clone_from_slice(&checksum_decoded) {
         remove_file(&ytdlp_path)?;
         ytdlp_path.pop();
-        download_ytdlp(ytdlp_path.into_os_string().into_string().unwrap())?;
+        download_ytdlp(path_str(&ytdlp_path)?.to_string())?;
     }
     Ok(())
 }
 
 /// Handles error correction on commands
-fn command_error_print(command: Output) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+///
+/// `to_error` builds the stage-specific error from the exit code and stderr.
+pub(crate) fn command_error_print(
+    command: Output,
+    to_error: impl FnOnce(Option<i32>, String) -> Error,
+) -> Result<()> {
     if !command.status.success() {
-        return Err(Box::new(std::io::Error::other(format!(
-            "Command error: {}",
-            String::from_utf8_lossy(&command.stderr)
-        ))));
+        return Err(to_error(
+            command.status.code(),
+            String::from_utf8_lossy(&command.stderr).into_owned(),
+        ));
     }
     Ok(())
 }
+
+/// Maps a failed `curl`/`chmod` call while fetching yt-dlp to [`Error::YtDlpSetup`].
+fn setup_error(_status: Option<i32>, stderr: String) -> Error {
+    Error::YtDlpSetup(stderr)
+}
+
+/// Borrow a path as UTF-8, which `Command` arguments here require.
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidFilename,
+            "Invalid UTF-8 in file path",
+        ))
+    })
+}